@@ -0,0 +1,389 @@
+use std::sync::OnceLock;
+
+use crate::{Board, CoordinateSet, Direction, Piece, PieceColor, PieceType, PieceType::*};
+
+/// Bit index of a square, uses the same x/y layout as Board so it doesn't depend on
+/// which side is drawn at the bottom ///
+pub(crate) fn square_index(square: &CoordinateSet) -> usize {
+    (square.y * 8 + square.x) as usize
+}
+pub(crate) fn index_square(index: usize) -> CoordinateSet {
+    CoordinateSet::new(index as i32 % 8, index as i32 / 8)
+}
+
+/// Iterates over the indices of the set bits, lowest first ///
+pub(crate) struct Bits(pub(crate) u64);
+impl Iterator for Bits {
+    type Item = usize;
+    fn next(&mut self) -> Option<usize> {
+        if self.0 == 0 {
+            return None;
+        }
+        let index = self.0.trailing_zeros() as usize;
+        self.0 &= self.0 - 1;
+        Some(index)
+    }
+}
+
+pub(crate) fn piece_index(piece_type: PieceType) -> Option<usize> {
+    match piece_type {
+        Pawn => Some(0),
+        Knight => Some(1),
+        Bishop => Some(2),
+        Rook { .. } => Some(3),
+        Queen => Some(4),
+        King { .. } => Some(5),
+        Empty => None,
+    }
+}
+pub(crate) fn color_index(color: PieceColor) -> usize {
+    match color {
+        PieceColor::White => 0,
+        PieceColor::Black => 1,
+    }
+}
+
+/// One u64 per piece type and per color, kept in sync with the mailbox Board ///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct Bitboards {
+    pieces: [u64; 6],
+    colors: [u64; 2],
+}
+
+impl Bitboards {
+    pub(crate) fn from_board(board: &Board) -> Bitboards {
+        let mut bitboards = Bitboards::default();
+        for (i, row) in board.iter().enumerate() {
+            for (j, piece) in row.iter().enumerate() {
+                bitboards.toggle(i * 8 + j, piece);
+            }
+        }
+        bitboards
+    }
+    /// Adds piece to an empty square or removes it from the square it is on ///
+    pub(crate) fn toggle(&mut self, index: usize, piece: &Piece) {
+        if let Some(piece_index) = piece_index(piece.piece_type) {
+            self.pieces[piece_index] ^= 1 << index;
+            self.colors[color_index(piece.color)] ^= 1 << index;
+        }
+    }
+    pub(crate) fn color(&self, color: PieceColor) -> u64 {
+        self.colors[color_index(color)]
+    }
+    pub(crate) fn pieces(&self, color: PieceColor, piece_type: PieceType) -> u64 {
+        piece_index(piece_type).map_or(0, |piece_index| {
+            self.pieces[piece_index] & self.color(color)
+        })
+    }
+    pub(crate) fn occupied(&self) -> u64 {
+        self.colors[0] | self.colors[1]
+    }
+    pub(crate) fn king_square(&self, color: PieceColor) -> Option<usize> {
+        Bits(self.pieces(color, King { has_moved: true })).next()
+    }
+    /// Whether any piece of color attacking_color could capture on index ///
+    pub(crate) fn is_attacked(&self, index: usize, attacking_color: PieceColor) -> bool {
+        let tables = tables();
+        let occupied = self.occupied();
+        let queens = self.pieces(attacking_color, Queen);
+        // A pawn attacks index from wherever a pawn of the other color on index would attack
+        tables.pawn[color_index(!attacking_color)][index] & self.pieces(attacking_color, Pawn) != 0
+            || tables.knight[index] & self.pieces(attacking_color, Knight) != 0
+            || tables.king[index] & self.pieces(attacking_color, King { has_moved: true }) != 0
+            || bishop_attacks(index, occupied) & (self.pieces(attacking_color, Bishop) | queens)
+                != 0
+            || rook_attacks(index, occupied)
+                & (self.pieces(attacking_color, Rook { has_moved: true }) | queens)
+                != 0
+    }
+}
+
+struct AttackTables {
+    knight: [u64; 64],
+    king: [u64; 64],
+    /// Squares a pawn of each color attacks from each square ///
+    pawn: [[u64; 64]; 2],
+    /// Squares along each of ROOK_DIRECTIONS then BISHOP_DIRECTIONS, excluding the start ///
+    rays: [[u64; 64]; 8],
+}
+
+const ROOK_DIRECTIONS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+const BISHOP_DIRECTIONS: [(i32, i32); 4] = [(1, 1), (-1, 1), (1, -1), (-1, -1)];
+const KNIGHT_JUMPS: [(i32, i32); 8] = [
+    (-1, 2),
+    (1, 2),
+    (-1, -2),
+    (1, -2),
+    (2, -1),
+    (2, 1),
+    (-2, -1),
+    (-2, 1),
+];
+
+fn tables() -> &'static AttackTables {
+    static TABLES: OnceLock<AttackTables> = OnceLock::new();
+    TABLES.get_or_init(|| {
+        let mut tables = AttackTables {
+            knight: [0; 64],
+            king: [0; 64],
+            pawn: [[0; 64]; 2],
+            rays: [[0; 64]; 8],
+        };
+        let directions: Vec<(i32, i32)> = ROOK_DIRECTIONS
+            .iter()
+            .chain(BISHOP_DIRECTIONS.iter())
+            .copied()
+            .collect();
+        let bit = |square: CoordinateSet| {
+            if square.out_of_bounds() {
+                0
+            } else {
+                1 << square_index(&square)
+            }
+        };
+        for index in 0..64 {
+            let square = index_square(index);
+            for (x, y) in KNIGHT_JUMPS {
+                tables.knight[index] |= bit(square + Direction { x, y });
+            }
+            for (i, &(x, y)) in directions.iter().enumerate() {
+                tables.king[index] |= bit(square + Direction { x, y });
+                let mut target = square + Direction { x, y };
+                while !target.out_of_bounds() {
+                    tables.rays[i][index] |= bit(target);
+                    target = target + Direction { x, y };
+                }
+            }
+            for color in [PieceColor::White, PieceColor::Black] {
+                let forward = Piece::new(color, Pawn).forward();
+                for x in [-1, 1] {
+                    tables.pawn[color_index(color)][index] |=
+                        bit(square + Direction { x, y: forward });
+                }
+            }
+        }
+        tables
+    })
+}
+
+/// Attacks along one ray, stopping at (and including) the first blocker ///
+fn ray_attacks(ray: usize, index: usize, occupied: u64) -> u64 {
+    let rays = &tables().rays[ray];
+    let attacks = rays[index];
+    let blockers = attacks & occupied;
+    if blockers == 0 {
+        return attacks;
+    }
+    let (x, y) = if ray < 4 {
+        ROOK_DIRECTIONS[ray]
+    } else {
+        BISHOP_DIRECTIONS[ray - 4]
+    };
+    // Rays going towards higher indices hit their lowest blocker first
+    let first_blocker = if y > 0 || (y == 0 && x > 0) {
+        blockers.trailing_zeros() as usize
+    } else {
+        63 - blockers.leading_zeros() as usize
+    };
+    attacks & !rays[first_blocker]
+}
+pub(crate) fn rook_attacks(index: usize, occupied: u64) -> u64 {
+    (0..4).fold(0, |acc, ray| acc | ray_attacks(ray, index, occupied))
+}
+pub(crate) fn bishop_attacks(index: usize, occupied: u64) -> u64 {
+    (4..8).fold(0, |acc, ray| acc | ray_attacks(ray, index, occupied))
+}
+pub(crate) fn knight_attacks(index: usize) -> u64 {
+    tables().knight[index]
+}
+pub(crate) fn king_attacks(index: usize) -> u64 {
+    tables().king[index]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use crate::{BoardPosition, ChessMove, CoordinateSet, Move, Piece, PieceColor, PieceType::*};
+
+    const POSITIONS: [&str; 6] = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    ];
+
+    /// The array board attack detection bitboards replaced, kept as a reference ///
+    fn mailbox_is_square_attacked(
+        position: &BoardPosition,
+        square: &CoordinateSet,
+        attacking_color: PieceColor,
+    ) -> bool {
+        let attacker_on = |direction: &crate::Direction, piece_type| {
+            let from = square + direction;
+            !from.out_of_bounds()
+                && *position.get_piece(&from) == Piece::new(attacking_color, piece_type)
+        };
+        let pawn_forward = Piece::new(attacking_color, Pawn).forward();
+        for x in [-1, 1] {
+            let direction = crate::Direction {
+                x,
+                y: -pawn_forward,
+            };
+            if attacker_on(&direction, Pawn) {
+                return true;
+            }
+        }
+        for ChessMove(_, direction) in Piece::new(attacking_color, Knight).get_moves() {
+            if attacker_on(&direction, Knight) {
+                return true;
+            }
+        }
+        for ChessMove(_, direction) in Piece::new(attacking_color, Queen).get_moves() {
+            if attacker_on(&direction, King { has_moved: true })
+                || attacker_on(&direction, King { has_moved: false })
+            {
+                return true;
+            }
+            let diagonal = direction.x != 0 && direction.y != 0;
+            let mut from = square + &direction;
+            while !from.out_of_bounds() {
+                let piece = position.get_piece(&from);
+                if !piece.is_empty() {
+                    if piece.color != attacking_color {
+                        break;
+                    }
+                    match piece.piece_type {
+                        Queen => return true,
+                        Bishop if diagonal => return true,
+                        Rook { .. } if !diagonal => return true,
+                        _ => break,
+                    }
+                }
+                from = from + &direction;
+            }
+        }
+        false
+    }
+
+    /// The array board move generation, walking every square and every ChessMove ///
+    fn mailbox_eval_moves(position: &mut BoardPosition) -> Vec<Move> {
+        let mut moves = Vec::new();
+        for y in 0..8 {
+            for x in 0..8 {
+                let square = CoordinateSet::new(x, y);
+                let piece = *position.get_piece(&square);
+                if piece.is_empty() || piece.color != position.side_to_move {
+                    continue;
+                }
+                for chess_move in piece.get_moves() {
+                    position.eval_move(&square, &chess_move, &mut moves);
+                }
+            }
+        }
+        moves
+    }
+
+    fn all_squares() -> impl Iterator<Item = CoordinateSet> {
+        (0..8).flat_map(|y| (0..8).map(move |x| CoordinateSet::new(x, y)))
+    }
+
+    #[test]
+    fn attacks_match_mailbox() {
+        for fen in POSITIONS {
+            let position = BoardPosition::from_fen(fen).unwrap();
+            for square in all_squares() {
+                for color in [PieceColor::White, PieceColor::Black] {
+                    assert_eq!(
+                        position.is_square_attacked(&square, color),
+                        mailbox_is_square_attacked(&position, &square, color),
+                        "{:?} attacking {} in {}",
+                        color,
+                        square.to_algebraic(),
+                        fen
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn moves_match_mailbox() {
+        for fen in POSITIONS {
+            let mut position = BoardPosition::from_fen(fen).unwrap();
+            let mut bitboard_moves: Vec<String> =
+                position.eval_moves().iter().map(Move::to_string).collect();
+            let mut mailbox_moves: Vec<String> = mailbox_eval_moves(&mut position)
+                .iter()
+                .map(Move::to_string)
+                .collect();
+            bitboard_moves.sort();
+            mailbox_moves.sort();
+            assert_eq!(bitboard_moves, mailbox_moves, "{}", fen);
+        }
+    }
+
+    #[test]
+    #[ignore = "benchmark, run with --release -- --ignored --nocapture"]
+    fn bench_against_mailbox() {
+        let iterations = 2000;
+        let mut positions: Vec<BoardPosition> = POSITIONS
+            .iter()
+            .map(|fen| BoardPosition::from_fen(fen).unwrap())
+            .collect();
+
+        let start = Instant::now();
+        let mut attacked = 0;
+        for _ in 0..iterations {
+            for position in &positions {
+                for square in all_squares() {
+                    attacked +=
+                        mailbox_is_square_attacked(position, &square, PieceColor::White) as usize;
+                }
+            }
+        }
+        let mailbox_attacks = start.elapsed();
+        let start = Instant::now();
+        for _ in 0..iterations {
+            for position in &positions {
+                for square in all_squares() {
+                    attacked -= position.is_square_attacked(&square, PieceColor::White) as usize;
+                }
+            }
+        }
+        let bitboard_attacks = start.elapsed();
+        assert_eq!(attacked, 0);
+
+        let start = Instant::now();
+        let mut generated = 0;
+        for _ in 0..iterations {
+            for position in positions.iter_mut() {
+                generated += mailbox_eval_moves(position).len();
+            }
+        }
+        let mailbox_moves = start.elapsed();
+        let start = Instant::now();
+        for _ in 0..iterations {
+            for position in positions.iter_mut() {
+                generated -= position.eval_moves().len();
+            }
+        }
+        let bitboard_moves = start.elapsed();
+        assert_eq!(generated, 0);
+
+        println!(
+            "Attack detection: array board {:?}, bitboards {:?} ({:.1}x)",
+            mailbox_attacks,
+            bitboard_attacks,
+            mailbox_attacks.as_secs_f64() / bitboard_attacks.as_secs_f64()
+        );
+        println!(
+            "Move generation: array board {:?}, bitboards {:?} ({:.1}x)",
+            mailbox_moves,
+            bitboard_moves,
+            mailbox_moves.as_secs_f64() / bitboard_moves.as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,129 @@
+use crate::{BoardPosition, DrawReason, PieceColor, PieceType::*};
+
+/// Squares the same color as a8, indexed like bitboard::square_index ///
+const LIGHT_SQUARES: u64 = 0xAA55AA55AA55AA55;
+/// The fifty-move rule counts moves by both sides ///
+const FIFTY_MOVES: u32 = 100;
+
+impl BoardPosition {
+    /// How many times this position was seen before, only looking back to the last capture or pawn move ///
+    pub(crate) fn repetitions(&self) -> usize {
+        self.position_hashes
+            .iter()
+            .rev()
+            .take(self.halfmove_clock as usize)
+            // Only positions with the same side to move can be equal
+            .skip(1)
+            .step_by(2)
+            .filter(|&&earlier| earlier == self.hash)
+            .count()
+    }
+
+    /// Neither side has enough material left to ever checkmate ///
+    pub(crate) fn has_insufficient_material(&self) -> bool {
+        let both = |piece_type| {
+            self.bitboards.pieces(PieceColor::White, piece_type)
+                | self.bitboards.pieces(PieceColor::Black, piece_type)
+        };
+        if both(Pawn) | both(Rook { has_moved: true }) | both(Queen) != 0 {
+            return false;
+        }
+        let knights = both(Knight);
+        let bishops = both(Bishop);
+        if (knights | bishops).count_ones() <= 1 {
+            return true;
+        }
+        // Bishops that all stand on the same color can't cover the king's escape squares
+        knights == 0 && (bishops & LIGHT_SQUARES == 0 || bishops & !LIGHT_SQUARES == 0)
+    }
+
+    /// The draw the game ends in, checkmate and stalemate aside ///
+    pub(crate) fn draw_reason(&self) -> Option<DrawReason> {
+        if self.has_insufficient_material() {
+            Some(DrawReason::InsufficientMaterial)
+        } else if self.halfmove_clock >= FIFTY_MOVES {
+            Some(DrawReason::FiftyMoves)
+        } else if self.repetitions() >= 2 {
+            Some(DrawReason::Repetition)
+        } else {
+            None
+        }
+    }
+
+    /// Search treats the first repetition as a draw, since whoever repeated could repeat again ///
+    pub(crate) fn is_search_draw(&self) -> bool {
+        self.halfmove_clock >= FIFTY_MOVES
+            || self.repetitions() >= 1
+            || self.has_insufficient_material()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fen::STARTING_FEN;
+    use crate::{BoardPosition, DrawReason, GameResult};
+
+    fn play(position: &mut BoardPosition, moves: &[&str]) {
+        for &move_text in moves {
+            let chess_move = position.parse_long_algebraic(move_text).unwrap();
+            position.make_move(&chess_move);
+        }
+    }
+
+    #[test]
+    fn threefold_repetition() {
+        let mut position = BoardPosition::from_fen(STARTING_FEN).unwrap();
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        play(&mut position, &shuffle);
+        assert_eq!(position.repetitions(), 1);
+        assert!(position.is_search_draw());
+        assert_eq!(position.game_result(), None);
+        play(&mut position, &shuffle);
+        assert_eq!(position.repetitions(), 2);
+        assert_eq!(
+            position.game_result(),
+            Some(GameResult::Draw(DrawReason::Repetition))
+        );
+
+        // A pawn move can't be undone, so nothing before it can repeat
+        play(&mut position, &["e2e4", "e7e5"]);
+        play(&mut position, &shuffle);
+        assert_eq!(position.repetitions(), 1);
+    }
+
+    #[test]
+    fn fifty_moves() {
+        let mut position = BoardPosition::from_fen("4k3/8/8/8/8/8/4P3/R3K3 w - - 99 80").unwrap();
+        let mut after_pawn = position.clone();
+        play(&mut position, &["a1a2"]);
+        assert_eq!(
+            position.game_result(),
+            Some(GameResult::Draw(DrawReason::FiftyMoves))
+        );
+        play(&mut after_pawn, &["e2e4"]);
+        assert_eq!(after_pawn.game_result(), None);
+    }
+
+    #[test]
+    fn insufficient_material() {
+        for (fen, insufficient) in [
+            ("4k3/8/8/8/8/8/8/4K3 w - - 0 1", true),
+            ("4k3/8/8/8/8/8/8/2B1K3 w - - 0 1", true),
+            ("4k3/8/8/8/8/8/8/1N2K3 w - - 0 1", true),
+            ("2b1k3/8/8/8/8/8/8/3BK3 w - - 0 1", true),
+            ("2b1k3/8/8/8/8/8/8/2B1K3 w - - 0 1", false),
+            ("4k3/8/8/8/8/8/8/1NB1K3 w - - 0 1", false),
+            ("4k3/8/8/8/8/8/8/1NN1K3 w - - 0 1", false),
+            ("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1", false),
+            ("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", false),
+        ] {
+            let position = BoardPosition::from_fen(fen).unwrap();
+            assert_eq!(
+                position.has_insufficient_material(),
+                insufficient,
+                "{}",
+                fen
+            );
+        }
+    }
+}
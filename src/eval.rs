@@ -0,0 +1,445 @@
+use std::ops::{Add, AddAssign, Mul, Sub};
+
+use crate::bitboard::{
+    bishop_attacks, index_square, king_attacks, knight_attacks, piece_index, rook_attacks, Bits,
+};
+use crate::{BoardPosition, Piece, PieceColor, PieceType, PieceType::*};
+
+/// Which evaluation terms are counted, material always is. Turning terms off
+/// makes it possible to test what each of them is worth ///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct EvalTerms {
+    pub(crate) piece_squares: bool,
+    pub(crate) pawn_structure: bool,
+    pub(crate) mobility: bool,
+    pub(crate) king_safety: bool,
+}
+
+impl Default for EvalTerms {
+    fn default() -> Self {
+        EvalTerms {
+            piece_squares: true,
+            pawn_structure: true,
+            mobility: true,
+            king_safety: true,
+        }
+    }
+}
+
+/// A score in centipawns for the middlegame and for the endgame, eval blends the two by game phase ///
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Score {
+    mg: i32,
+    eg: i32,
+}
+
+const fn s(mg: i32, eg: i32) -> Score {
+    Score { mg, eg }
+}
+
+impl Add for Score {
+    type Output = Score;
+    fn add(self, other: Score) -> Score {
+        s(self.mg + other.mg, self.eg + other.eg)
+    }
+}
+impl AddAssign for Score {
+    fn add_assign(&mut self, other: Score) {
+        *self = *self + other;
+    }
+}
+impl Sub for Score {
+    type Output = Score;
+    fn sub(self, other: Score) -> Score {
+        s(self.mg - other.mg, self.eg - other.eg)
+    }
+}
+impl Mul<i32> for Score {
+    type Output = Score;
+    fn mul(self, factor: i32) -> Score {
+        s(self.mg * factor, self.eg * factor)
+    }
+}
+
+const PIECE_TYPES: [PieceType; 6] = [
+    Pawn,
+    Knight,
+    Bishop,
+    Rook { has_moved: true },
+    Queen,
+    King { has_moved: true },
+];
+/// Indexed like bitboard::piece_index ///
+const MATERIAL: [Score; 6] = [
+    s(82, 94),
+    s(337, 281),
+    s(365, 297),
+    s(477, 512),
+    s(1025, 936),
+    s(0, 0),
+];
+/// Per square a piece attacks that isn't taken by its own side, indexed like MATERIAL ///
+const MOBILITY: [Score; 6] = [s(0, 0), s(4, 4), s(5, 5), s(2, 4), s(1, 2), s(0, 0)];
+/// Non-pawn material both sides start with, counted with Piece::point_value ///
+const FULL_PHASE: i32 = 62;
+
+const DOUBLED_PAWN: Score = s(-10, -20);
+const ISOLATED_PAWN: Score = s(-10, -15);
+/// By how far the pawn has advanced, 0 being its own back rank ///
+const PASSED_PAWN: [Score; 8] = [
+    s(0, 0),
+    s(5, 10),
+    s(5, 15),
+    s(10, 25),
+    s(20, 45),
+    s(35, 75),
+    s(60, 120),
+    s(0, 0),
+];
+/// For each pawn on a square next to the king or the one beyond it ///
+const PAWN_SHIELD: [Score; 2] = [s(12, 0), s(6, 0)];
+/// For each file next to or under the king without a pawn of the king's side ///
+const OPEN_KING_FILE: Score = s(-15, 0);
+/// For each attack by an enemy piece on a square next to the king ///
+const KING_ZONE_ATTACK: Score = s(-8, 0);
+
+const FILE_A: u64 = 0x0101010101010101;
+
+/// Bonus for a piece on each square, from White's side with a8 first so they read like a board ///
+type Table = [i32; 64];
+#[rustfmt::skip]
+const PAWN_MG: Table = [
+     0,   0,   0,   0,   0,   0,   0,   0,
+    50,  50,  50,  50,  50,  50,  50,  50,
+    10,  10,  20,  30,  30,  20,  10,  10,
+     5,   5,  10,  25,  25,  10,   5,   5,
+     0,   0,   0,  20,  20,   0,   0,   0,
+     5,  -5, -10,   0,   0, -10,  -5,   5,
+     5,  10,  10, -20, -20,  10,  10,   5,
+     0,   0,   0,   0,   0,   0,   0,   0,
+];
+#[rustfmt::skip]
+const PAWN_EG: Table = [
+     0,   0,   0,   0,   0,   0,   0,   0,
+    80,  80,  80,  80,  80,  80,  80,  80,
+    50,  50,  50,  50,  50,  50,  50,  50,
+    30,  30,  30,  30,  30,  30,  30,  30,
+    15,  15,  15,  15,  15,  15,  15,  15,
+     5,   5,   5,   5,   5,   5,   5,   5,
+     0,   0,   0,   0,   0,   0,   0,   0,
+     0,   0,   0,   0,   0,   0,   0,   0,
+];
+#[rustfmt::skip]
+const KNIGHT: Table = [
+   -50, -40, -30, -30, -30, -30, -40, -50,
+   -40, -20,   0,   0,   0,   0, -20, -40,
+   -30,   0,  10,  15,  15,  10,   0, -30,
+   -30,   5,  15,  20,  20,  15,   5, -30,
+   -30,   0,  15,  20,  20,  15,   0, -30,
+   -30,   5,  10,  15,  15,  10,   5, -30,
+   -40, -20,   0,   5,   5,   0, -20, -40,
+   -50, -40, -30, -30, -30, -30, -40, -50,
+];
+#[rustfmt::skip]
+const BISHOP: Table = [
+   -20, -10, -10, -10, -10, -10, -10, -20,
+   -10,   0,   0,   0,   0,   0,   0, -10,
+   -10,   0,   5,  10,  10,   5,   0, -10,
+   -10,   5,   5,  10,  10,   5,   5, -10,
+   -10,   0,  10,  10,  10,  10,   0, -10,
+   -10,  10,  10,  10,  10,  10,  10, -10,
+   -10,   5,   0,   0,   0,   0,   5, -10,
+   -20, -10, -10, -10, -10, -10, -10, -20,
+];
+#[rustfmt::skip]
+const ROOK: Table = [
+     0,   0,   0,   0,   0,   0,   0,   0,
+     5,  10,  10,  10,  10,  10,  10,   5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+     0,   0,   0,   5,   5,   0,   0,   0,
+];
+#[rustfmt::skip]
+const QUEEN: Table = [
+   -20, -10, -10,  -5,  -5, -10, -10, -20,
+   -10,   0,   0,   0,   0,   0,   0, -10,
+   -10,   0,   5,   5,   5,   5,   0, -10,
+    -5,   0,   5,   5,   5,   5,   0,  -5,
+     0,   0,   5,   5,   5,   5,   0,  -5,
+   -10,   5,   5,   5,   5,   5,   0, -10,
+   -10,   0,   5,   0,   0,   0,   0, -10,
+   -20, -10, -10,  -5,  -5, -10, -10, -20,
+];
+#[rustfmt::skip]
+const KING_MG: Table = [
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -20, -30, -30, -40, -40, -30, -30, -20,
+   -10, -20, -20, -20, -20, -20, -20, -10,
+    20,  20,   0,   0,   0,   0,  20,  20,
+    20,  30,  10,   0,   0,  10,  30,  20,
+];
+#[rustfmt::skip]
+const KING_EG: Table = [
+   -50, -40, -30, -20, -20, -30, -40, -50,
+   -30, -20, -10,   0,   0, -10, -20, -30,
+   -30, -10,  20,  30,  30,  20, -10, -30,
+   -30, -10,  30,  40,  40,  30, -10, -30,
+   -30, -10,  30,  40,  40,  30, -10, -30,
+   -30, -10,  20,  30,  30,  20, -10, -30,
+   -30, -30,   0,   0,   0,   0, -30, -30,
+   -50, -30, -30, -30, -30, -30, -30, -50,
+];
+
+/// The middlegame and endgame tables for a piece type ///
+fn tables(piece_type: PieceType) -> (&'static Table, &'static Table) {
+    match piece_type {
+        Pawn => (&PAWN_MG, &PAWN_EG),
+        Knight => (&KNIGHT, &KNIGHT),
+        Bishop => (&BISHOP, &BISHOP),
+        Rook { .. } => (&ROOK, &ROOK),
+        Queen => (&QUEEN, &QUEEN),
+        King { .. } => (&KING_MG, &KING_EG),
+        Empty => unreachable!("Empty squares have no table"),
+    }
+}
+
+/// Bit index as seen from color's side, so White's tables work for Black too ///
+fn relative_index(color: PieceColor, index: usize) -> usize {
+    match color {
+        PieceColor::White => index,
+        PieceColor::Black => index ^ 56,
+    }
+}
+
+/// Squares on the ranks in front of y from color's side ///
+fn ahead(color: PieceColor, y: i32) -> u64 {
+    match color {
+        PieceColor::White => (1 << (y * 8)) - 1,
+        PieceColor::Black => u64::MAX.checked_shl((y as u32 + 1) * 8).unwrap_or(0),
+    }
+}
+
+fn adjacent_files(file: i32) -> u64 {
+    let left = if file > 0 { FILE_A << (file - 1) } else { 0 };
+    let right = if file < 7 { FILE_A << (file + 1) } else { 0 };
+    left | right
+}
+
+impl BoardPosition {
+    /// Centipawn evaluation from color_moving's point of view ///
+    pub(crate) fn eval(&self, color_moving: PieceColor, terms: &EvalTerms) -> i32 {
+        let score =
+            self.side_score(PieceColor::White, terms) - self.side_score(PieceColor::Black, terms);
+        let phase = self.phase();
+        let blended = (score.mg * phase + score.eg * (FULL_PHASE - phase)) / FULL_PHASE;
+        match color_moving {
+            PieceColor::White => blended,
+            PieceColor::Black => -blended,
+        }
+    }
+
+    /// FULL_PHASE at the start of the game down to 0 when only kings and pawns are left ///
+    fn phase(&self) -> i32 {
+        self.board
+            .iter()
+            .flatten()
+            .filter(|piece| !matches!(piece.piece_type, Pawn | King { .. }))
+            .map(Piece::point_value)
+            .sum::<i32>()
+            .min(FULL_PHASE)
+    }
+
+    fn side_score(&self, color: PieceColor, terms: &EvalTerms) -> Score {
+        let own = self.bitboards.color(color);
+        let occupied = self.bitboards.occupied();
+        let mut score = Score::default();
+        for piece_type in PIECE_TYPES {
+            let piece_index = piece_index(piece_type).unwrap();
+            let (mg_table, eg_table) = tables(piece_type);
+            for index in Bits(self.bitboards.pieces(color, piece_type)) {
+                score += MATERIAL[piece_index];
+                if terms.piece_squares {
+                    let square = relative_index(color, index);
+                    score += s(mg_table[square], eg_table[square]);
+                }
+                if terms.mobility {
+                    let attacks = match piece_type {
+                        Knight => knight_attacks(index),
+                        Bishop => bishop_attacks(index, occupied),
+                        Rook { .. } => rook_attacks(index, occupied),
+                        Queen => bishop_attacks(index, occupied) | rook_attacks(index, occupied),
+                        _ => 0,
+                    };
+                    score += MOBILITY[piece_index] * (attacks & !own).count_ones() as i32;
+                }
+            }
+        }
+        if terms.pawn_structure {
+            score += self.pawn_structure(color);
+        }
+        if terms.king_safety {
+            score += self.king_safety(color);
+        }
+        score
+    }
+
+    /// Doubled and isolated pawns are weak, passed pawns get stronger as they advance ///
+    fn pawn_structure(&self, color: PieceColor) -> Score {
+        let pawns = self.bitboards.pieces(color, Pawn);
+        let enemy_pawns = self.bitboards.pieces(!color, Pawn);
+        let mut score = Score::default();
+        for file in 0..8 {
+            let on_file = (pawns & FILE_A << file).count_ones() as i32;
+            if on_file > 1 {
+                score += DOUBLED_PAWN * (on_file - 1);
+            }
+            if pawns & adjacent_files(file) == 0 {
+                score += ISOLATED_PAWN * on_file;
+            }
+        }
+        for index in Bits(pawns) {
+            let square = index_square(index);
+            let in_front = (FILE_A << square.x | adjacent_files(square.x)) & ahead(color, square.y);
+            if enemy_pawns & in_front == 0 {
+                score += PASSED_PAWN[(relative_index(color, index) / 8) ^ 7];
+            }
+        }
+        score
+    }
+
+    /// Pawns in front of the king shelter it, open files next to it and enemy pieces
+    /// attacking the squares around it are dangerous. Only counts in the middlegame ///
+    fn king_safety(&self, color: PieceColor) -> Score {
+        let Some(king) = self.bitboards.king_square(color) else {
+            return Score::default();
+        };
+        let king_square = index_square(king);
+        let pawns = self.bitboards.pieces(color, Pawn);
+        let zone = king_attacks(king);
+        let mut score = Score::default();
+
+        let shield = zone & ahead(color, king_square.y);
+        let beyond = match color {
+            PieceColor::White => shield >> 8,
+            PieceColor::Black => shield << 8,
+        };
+        score += PAWN_SHIELD[0] * (pawns & shield).count_ones() as i32;
+        score += PAWN_SHIELD[1] * (pawns & beyond & !shield).count_ones() as i32;
+
+        for file in (king_square.x - 1).max(0)..=(king_square.x + 1).min(7) {
+            if pawns & FILE_A << file == 0 {
+                score += OPEN_KING_FILE;
+            }
+        }
+
+        let occupied = self.bitboards.occupied();
+        let attacker = |piece_type| Bits(self.bitboards.pieces(!color, piece_type));
+        let mut attacks = 0;
+        for index in attacker(Knight) {
+            attacks += (knight_attacks(index) & zone).count_ones();
+        }
+        for index in attacker(Bishop).chain(attacker(Queen)) {
+            attacks += (bishop_attacks(index, occupied) & zone).count_ones();
+        }
+        for index in attacker(Rook { has_moved: true }).chain(attacker(Queen)) {
+            attacks += (rook_attacks(index, occupied) & zone).count_ones();
+        }
+        score += KING_ZONE_ATTACK * attacks as i32;
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::STARTING_FEN;
+
+    fn eval(fen: &str, terms: &EvalTerms) -> i32 {
+        BoardPosition::from_fen(fen)
+            .unwrap()
+            .eval(PieceColor::White, terms)
+    }
+
+    fn only(term: fn(&mut EvalTerms)) -> EvalTerms {
+        let mut terms = EvalTerms {
+            piece_squares: false,
+            pawn_structure: false,
+            mobility: false,
+            king_safety: false,
+        };
+        term(&mut terms);
+        terms
+    }
+
+    #[test]
+    fn symmetric() {
+        let all = EvalTerms::default();
+        assert_eq!(eval(STARTING_FEN, &all), 0);
+        for (fen, mirrored) in [
+            (
+                "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+                "rnbqk2r/pppp1ppp/5n2/2b1p3/4P3/2N2N2/PPPP1PPP/R1BQKB1R b KQkq - 4 4",
+            ),
+            (
+                "8/2k5/3p4/1P6/8/6K1/7P/8 w - - 0 1",
+                "8/7p/6k1/8/1p6/3P4/2K5/8 b - - 0 1",
+            ),
+        ] {
+            let position = BoardPosition::from_fen(fen).unwrap();
+            let mirrored = BoardPosition::from_fen(mirrored).unwrap();
+            assert_eq!(
+                position.eval(PieceColor::White, &all),
+                mirrored.eval(PieceColor::Black, &all)
+            );
+            assert_eq!(
+                position.eval(PieceColor::White, &all),
+                -position.eval(PieceColor::Black, &all)
+            );
+        }
+    }
+
+    #[test]
+    fn material_only() {
+        let none = only(|_| {});
+        // With only pawns left the endgame values are used as they are
+        assert_eq!(eval("4k3/8/8/8/8/8/P7/4K3 w - - 0 1", &none), 94);
+        // Without the queen the phase is 53 of 62, so mostly middlegame values
+        assert_eq!(
+            eval(
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNB1KBNR w KQkq - 0 1",
+                &none
+            ),
+            (-1025 * 53 - 936 * 9) / 62
+        );
+    }
+
+    #[test]
+    fn terms() {
+        // A knight in the centre beats one in the corner
+        let piece_squares = only(|terms| terms.piece_squares = true);
+        assert!(
+            eval("4k3/8/8/8/3N4/8/8/4K3 w - - 0 1", &piece_squares)
+                > eval("4k3/8/8/8/8/8/8/N3K3 w - - 0 1", &piece_squares)
+        );
+
+        // Both pawns are passed but White's is further up, doubled pawns are worse
+        let pawn_structure = only(|terms| terms.pawn_structure = true);
+        assert!(eval("4k3/7p/8/P7/8/8/8/4K3 w - - 0 1", &pawn_structure) > 0);
+        assert!(eval("4k3/3pp3/8/8/8/8/3P4/3PK3 w - - 0 1", &pawn_structure) < 0);
+
+        let mobility = only(|terms| terms.mobility = true);
+        assert!(eval("4k3/8/8/8/3B4/8/8/B3K3 w - - 0 1", &mobility) > 0);
+        assert_eq!(eval(STARTING_FEN, &mobility), 0);
+
+        // Castled behind three pawns against a king with an open file, despite the queen nearby
+        let king_safety = only(|terms| terms.king_safety = true);
+        let fen = "5rk1/5p1p/6q1/8/8/8/5PPP/6K1 w - - 0 1";
+        assert!(eval(fen, &king_safety) > eval(fen, &only(|_| {})));
+    }
+}
@@ -0,0 +1,319 @@
+use crate::{BoardPosition, CoordinateSet, Piece, PieceColor, PieceType::*};
+
+/// Castling letters in FEN order with the king's and rook's starting file ///
+const CASTLING: [(char, PieceColor, i32); 4] = [
+    ('K', PieceColor::White, 7),
+    ('Q', PieceColor::White, 0),
+    ('k', PieceColor::Black, 7),
+    ('q', PieceColor::Black, 0),
+];
+const KING_FILE: i32 = 4;
+pub(crate) const STARTING_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+fn back_rank(color: PieceColor) -> i32 {
+    match color {
+        PieceColor::White => 0,
+        PieceColor::Black => 7,
+    }
+}
+
+fn piece_from_char(letter: char) -> Option<Piece> {
+    let color = if letter.is_ascii_uppercase() {
+        PieceColor::White
+    } else {
+        PieceColor::Black
+    };
+    // Castling rights are applied afterwards, so start everything as moved
+    let piece_type = match letter.to_ascii_lowercase() {
+        'p' => Pawn,
+        'n' => Knight,
+        'b' => Bishop,
+        'r' => Rook { has_moved: true },
+        'q' => Queen,
+        'k' => King { has_moved: true },
+        _ => return None,
+    };
+    Some(Piece::new(color, piece_type))
+}
+
+fn char_from_piece(piece: &Piece) -> char {
+    let letter = match piece.piece_type {
+        Pawn => 'p',
+        Knight => 'n',
+        Bishop => 'b',
+        Rook { .. } => 'r',
+        Queen => 'q',
+        King { .. } => 'k',
+        Empty => ' ',
+    };
+    match piece.color {
+        PieceColor::White => letter.to_ascii_uppercase(),
+        PieceColor::Black => letter,
+    }
+}
+
+impl BoardPosition {
+    pub(crate) fn from_fen(fen: &str) -> Result<BoardPosition, String> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.len() != 6 {
+            return Err(format!(
+                "FEN needs 6 space separated fields but has {}",
+                fields.len()
+            ));
+        }
+        let mut position = BoardPosition::default();
+        for rank in 0..8 {
+            for file in 0..8 {
+                position.clear_square(&CoordinateSet::from_file_rank(file, rank));
+            }
+        }
+
+        let ranks: Vec<&str> = fields[0].split('/').collect();
+        if ranks.len() != 8 {
+            return Err(format!(
+                "Piece placement needs 8 ranks but has {}",
+                ranks.len()
+            ));
+        }
+        for (i, rank_text) in ranks.iter().enumerate() {
+            let rank = 7 - i as i32;
+            let mut file = 0;
+            for letter in rank_text.chars() {
+                if let Some(skip) = letter.to_digit(10) {
+                    if !(1..=8).contains(&skip) {
+                        return Err(format!("Invalid empty square count '{}'", letter));
+                    }
+                    file += skip as i32;
+                    continue;
+                }
+                let piece = piece_from_char(letter)
+                    .ok_or_else(|| format!("Invalid piece '{}' on rank {}", letter, rank + 1))?;
+                if file > 7 {
+                    return Err(format!("Rank {} has more than 8 squares", rank + 1));
+                }
+                position.set_piece(&CoordinateSet::from_file_rank(file, rank), piece);
+                file += 1;
+            }
+            if file != 8 {
+                return Err(format!(
+                    "Rank {} has {} squares instead of 8",
+                    rank + 1,
+                    file
+                ));
+            }
+        }
+        for color in [PieceColor::White, PieceColor::Black] {
+            let kings = position
+                .board
+                .iter()
+                .flatten()
+                .filter(|piece| piece.color == color && matches!(piece.piece_type, King { .. }))
+                .count();
+            if kings != 1 {
+                return Err(format!("{:?} has {} kings instead of 1", color, kings));
+            }
+        }
+
+        let side_to_move = match fields[1] {
+            "w" => PieceColor::White,
+            "b" => PieceColor::Black,
+            other => return Err(format!("Invalid side to move '{}'", other)),
+        };
+        if position.is_in_check(!side_to_move) {
+            return Err(format!(
+                "{:?} is to move but {:?} is already in check",
+                side_to_move, !side_to_move
+            ));
+        }
+
+        if fields[2] != "-" {
+            for letter in fields[2].chars() {
+                let &(_, color, rook_file) = CASTLING
+                    .iter()
+                    .find(|(castle, _, _)| *castle == letter)
+                    .ok_or_else(|| format!("Invalid castling right '{}'", letter))?;
+                let king_square = CoordinateSet::from_file_rank(KING_FILE, back_rank(color));
+                let rook_square = CoordinateSet::from_file_rank(rook_file, back_rank(color));
+                let king = *position.get_piece(&king_square);
+                let rook = *position.get_piece(&rook_square);
+                if king.color != color || !matches!(king.piece_type, King { .. }) {
+                    return Err(format!(
+                        "Castling right '{}' needs a {:?} king on {}",
+                        letter,
+                        color,
+                        king_square.to_algebraic()
+                    ));
+                }
+                if rook.color != color || !matches!(rook.piece_type, Rook { .. }) {
+                    return Err(format!(
+                        "Castling right '{}' needs a {:?} rook on {}",
+                        letter,
+                        color,
+                        rook_square.to_algebraic()
+                    ));
+                }
+                position.set_piece(&king_square, Piece::new(color, King { has_moved: false }));
+                position.set_piece(&rook_square, Piece::new(color, Rook { has_moved: false }));
+            }
+        }
+
+        if fields[3] != "-" {
+            let target = CoordinateSet::from_algebraic(fields[3])
+                .ok_or_else(|| format!("Invalid en passant square '{}'", fields[3]))?;
+            // FEN names the square the pawn skipped, BoardPosition stores the pawn itself
+            let (file, rank) = target.file_rank();
+            let (expected_rank, pawn_rank) = match side_to_move {
+                PieceColor::White => (5, 4),
+                PieceColor::Black => (2, 3),
+            };
+            if rank != expected_rank {
+                return Err(format!(
+                    "En passant square {} is on the wrong rank for {:?} to move",
+                    fields[3], side_to_move
+                ));
+            }
+            let pawn_square = CoordinateSet::from_file_rank(file, pawn_rank);
+            if *position.get_piece(&pawn_square) != Piece::new(!side_to_move, Pawn) {
+                return Err(format!(
+                    "En passant square {} has no {:?} pawn on {}",
+                    fields[3],
+                    !side_to_move,
+                    pawn_square.to_algebraic()
+                ));
+            }
+            position.en_passante = Some(pawn_square);
+        }
+
+        let halfmove_clock = fields[4]
+            .parse()
+            .map_err(|_| format!("Invalid halfmove clock '{}'", fields[4]))?;
+        let fullmove_number = match fields[5].parse() {
+            Ok(number) if number > 0 => number,
+            _ => return Err(format!("Invalid fullmove number '{}'", fields[5])),
+        };
+
+        position.side_to_move = side_to_move;
+        position.halfmove_clock = halfmove_clock;
+        position.fullmove_number = fullmove_number;
+        position.hash = position.zobrist_hash();
+        Ok(position)
+    }
+
+    /// Castling rights in KQkq order, read off the unmoved kings and rooks ///
+    pub(crate) fn castling_rights(&self) -> [bool; 4] {
+        CASTLING.map(|(_, color, rook_file)| {
+            let rank = back_rank(color);
+            *self.get_piece(&CoordinateSet::from_file_rank(KING_FILE, rank))
+                == Piece::new(color, King { has_moved: false })
+                && *self.get_piece(&CoordinateSet::from_file_rank(rook_file, rank))
+                    == Piece::new(color, Rook { has_moved: false })
+        })
+    }
+
+    pub(crate) fn to_fen(&self) -> String {
+        let placement = (0..8)
+            .rev()
+            .map(|rank| {
+                let mut rank_text = String::new();
+                let mut empty = 0;
+                for file in 0..8 {
+                    let piece = self.get_piece(&CoordinateSet::from_file_rank(file, rank));
+                    if piece.is_empty() {
+                        empty += 1;
+                        continue;
+                    }
+                    if empty > 0 {
+                        rank_text.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    rank_text.push(char_from_piece(piece));
+                }
+                if empty > 0 {
+                    rank_text.push_str(&empty.to_string());
+                }
+                rank_text
+            })
+            .collect::<Vec<String>>()
+            .join("/");
+
+        let castling: String = CASTLING
+            .iter()
+            .zip(self.castling_rights())
+            .filter(|(_, allowed)| *allowed)
+            .map(|((letter, _, _), _)| *letter)
+            .collect();
+
+        let en_passante = match &self.en_passante {
+            Some(pawn_square) => {
+                let (file, rank) = pawn_square.file_rank();
+                let skipped_rank = match self.side_to_move {
+                    PieceColor::White => rank + 1,
+                    PieceColor::Black => rank - 1,
+                };
+                CoordinateSet::from_file_rank(file, skipped_rank).to_algebraic()
+            }
+            None => String::from("-"),
+        };
+
+        format!(
+            "{} {} {} {} {} {}",
+            placement,
+            match self.side_to_move {
+                PieceColor::White => "w",
+                PieceColor::Black => "b",
+            },
+            if castling.is_empty() {
+                String::from("-")
+            } else {
+                castling
+            },
+            en_passante,
+            self.halfmove_clock,
+            self.fullmove_number
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        for fen in [
+            STARTING_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w Kq f6 0 3",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 11 40",
+        ] {
+            assert_eq!(BoardPosition::from_fen(fen).unwrap().to_fen(), fen);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        for (fen, error) in [
+            ("8/8/8/8/8/8/8/4K2k w - - 0", "6 space separated fields"),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNRR w KQkq - 0 1",
+                "more than 8 squares",
+            ),
+            ("4k3/8/8/8/8/8/8/4K1K1 w - - 0 1", "White has 2 kings"),
+            ("8/8/8/8/8/8/8/4K3 w - - 0 1", "Black has 0 kings"),
+            ("4k3/8/8/8/8/8/8/4K3 w K - 0 1", "needs a White rook on h1"),
+            (
+                "3k3r/8/8/8/8/8/8/R3K3 w Qk - 0 1",
+                "needs a Black king on e8",
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - e9 0 1",
+                "Invalid en passant square",
+            ),
+            ("4k3/8/8/3pP3/8/8/8/4K3 w - d3 0 1", "wrong rank"),
+            ("4k3/8/8/4P3/8/8/8/4K3 w - d6 0 1", "no Black pawn on d5"),
+        ] {
+            let message = BoardPosition::from_fen(fen).unwrap_err();
+            assert!(message.contains(error), "{}: {}", fen, message);
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use crate::{BoardPosition, Move, PieceColor};
+
+/// Every position of the game so far with the moves between them ///
+#[derive(Debug, Clone)]
+pub(crate) struct GameHistory {
+    /// positions[i] is the position before moves[i], the last one is the current position ///
+    positions: Vec<BoardPosition>,
+    moves: Vec<Move>,
+    /// moves[i] in Standard Algebraic Notation ///
+    sans: Vec<String>,
+}
+
+impl GameHistory {
+    pub(crate) fn new(start: BoardPosition) -> GameHistory {
+        GameHistory {
+            positions: vec![start],
+            moves: Vec::new(),
+            sans: Vec::new(),
+        }
+    }
+
+    pub(crate) fn start(&self) -> &BoardPosition {
+        &self.positions[0]
+    }
+
+    pub(crate) fn current(&self) -> &BoardPosition {
+        self.positions.last().unwrap()
+    }
+
+    pub(crate) fn sans(&self) -> &[String] {
+        &self.sans
+    }
+
+    /// Records a move made on the current position ///
+    pub(crate) fn push(&mut self, chess_move: Move) {
+        let mut position = self.current().clone();
+        self.sans.push(position.san(&chess_move));
+        position.make_move(&chess_move);
+        self.positions.push(position);
+        self.moves.push(chess_move);
+    }
+
+    /// Takes back player's last move and any reply to it, so it is player's turn again.
+    /// Returns None if player hasn't moved yet.
+    pub(crate) fn takeback(&mut self, player: PieceColor) -> Option<&BoardPosition> {
+        let last_player_move = (0..self.moves.len())
+            .rev()
+            .find(|&i| self.positions[i].side_to_move == player)?;
+        self.positions.truncate(last_player_move + 1);
+        self.moves.truncate(last_player_move);
+        self.sans.truncate(last_player_move);
+        Some(self.current())
+    }
+
+    /// The moves numbered in pairs, like "1. e4 e5" ///
+    pub(crate) fn numbered_moves(&self) -> Vec<String> {
+        let mut lines: Vec<String> = Vec::new();
+        for (position, san) in self.positions.iter().zip(&self.sans) {
+            match position.side_to_move {
+                PieceColor::White => lines.push(format!("{}. {}", position.fullmove_number, san)),
+                PieceColor::Black => match lines.last_mut() {
+                    Some(line) => line.push_str(&format!(" {}", san)),
+                    None => lines.push(format!("{}... {}", position.fullmove_number, san)),
+                },
+            }
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::STARTING_FEN;
+
+    fn play(history: &mut GameHistory, moves: &[&str]) {
+        for &move_text in moves {
+            let chess_move = history
+                .current()
+                .clone()
+                .eval_moves()
+                .into_iter()
+                .find(|chess_move| chess_move.to_string() == move_text)
+                .unwrap();
+            history.push(chess_move);
+        }
+    }
+
+    #[test]
+    fn takeback_pairs() {
+        let start = BoardPosition::from_fen(STARTING_FEN).unwrap();
+        let mut history = GameHistory::new(start.clone());
+        assert!(history.takeback(PieceColor::White).is_none());
+
+        play(&mut history, &["e2e4", "e7e5", "g1f3", "b8c6"]);
+        assert_eq!(history.numbered_moves(), ["1. e4 e5", "2. Nf3 Nc6"]);
+        let after_one = history.takeback(PieceColor::White).unwrap().to_fen();
+        assert_eq!(
+            after_one,
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2"
+        );
+
+        // Black's takeback also removes White's reply to e7e5
+        play(&mut history, &["g1f3"]);
+        let black_turn = history.takeback(PieceColor::Black).unwrap();
+        assert_eq!(black_turn.side_to_move, PieceColor::Black);
+        assert_eq!(history.numbered_moves(), ["1. e4"]);
+
+        assert_eq!(
+            history.takeback(PieceColor::White).unwrap().to_fen(),
+            start.to_fen()
+        );
+        assert!(history.takeback(PieceColor::White).is_none());
+    }
+
+    #[test]
+    fn black_starts() {
+        let fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1";
+        let mut history = GameHistory::new(BoardPosition::from_fen(fen).unwrap());
+        play(&mut history, &["e7e5", "g1f3"]);
+        assert_eq!(history.numbered_moves(), ["1... e5", "2. Nf3"]);
+    }
+}
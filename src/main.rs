@@ -1,12 +1,37 @@
-use core::{panic, time};
+mod bitboard;
+mod draw;
+mod eval;
+mod fen;
+mod history;
+mod move_ordering;
+mod notation;
+mod perft;
+mod pgn;
+mod ponder;
+mod search;
+mod time_manager;
+mod transposition;
+mod uci;
+mod zobrist;
+
+use bitboard::{Bitboards, Bits};
+use core::panic;
+use eval::EvalTerms;
+use history::GameHistory;
+use ponder::PonderSearch;
+use search::{SearchOptions, SearchResult, Searcher, MAX_DEPTH, MAX_THREADS};
 use std::cmp::min;
 use std::convert::From;
+use std::fmt;
 use std::io::stdin;
 use std::ops::Not;
-use std::sync::mpsc::{self, Receiver, Sender};
-use std::thread::{self, sleep};
-use std::time::Instant;
-use std::{fmt, usize};
+use std::sync::atomic::AtomicBool;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use time_manager::{TimeBudget, TimeControl};
+use transposition::TranspositionTable;
 use PieceType::*;
 
 #[allow(dead_code)]
@@ -26,7 +51,6 @@ impl Not for PieceColor {
     }
 }
 
-const BOTTOM_SIDE: PieceColor = PieceColor::White;
 #[allow(dead_code)]
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 enum PieceType {
@@ -39,8 +63,9 @@ enum PieceType {
     Empty,
 }
 impl PieceType {
+    /// Pieces a pawn can promote to, strongest first ///
     fn to_promote() -> [PieceType; 4] {
-        [Knight, Bishop, Rook { has_moved: true }, Queen]
+        [Queen, Rook { has_moved: true }, Bishop, Knight]
     }
 }
 #[rustfmt::skip]
@@ -54,7 +79,7 @@ const INITIAL_BOARD: [[(PieceColor,PieceType); 8]; 8] = [
     [(PieceColor::White,Pawn),(PieceColor::White,Pawn),(PieceColor::White,Pawn),(PieceColor::White,Pawn),(PieceColor::White,Pawn),(PieceColor::White,Pawn),(PieceColor::White,Pawn),(PieceColor::White,Pawn)],
     [(PieceColor::White,Rook { has_moved: false }),(PieceColor::White,Knight),(PieceColor::White,Bishop),(PieceColor::White,Queen),(PieceColor::White,King { has_moved: false }),(PieceColor::White,Bishop),(PieceColor::White,Knight),(PieceColor::White,Rook { has_moved: false })],
 ];
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 struct CoordinateSet {
     x: i32,
     y: i32,
@@ -74,6 +99,40 @@ impl CoordinateSet {
     fn out_of_bounds(&self) -> bool {
         self.x < BOUNDS.0 || self.x > BOUNDS.1 || self.y < BOUNDS.0 || self.y > BOUNDS.1
     }
+    /// file and rank both count from 0, so a1 is (0, 0) and h8 is (7, 7).
+    /// Internally y counts down from rank 8, however the board is displayed.
+    fn from_file_rank(file: i32, rank: i32) -> CoordinateSet {
+        CoordinateSet::new(file, BOUNDS.1 - rank)
+    }
+    fn file_rank(&self) -> (i32, i32) {
+        (self.x, BOUNDS.1 - self.y)
+    }
+    /// Maps between internal and screen squares, which are the same unless the board is flipped ///
+    fn oriented(self, flipped: bool) -> CoordinateSet {
+        match flipped {
+            true => CoordinateSet::new(BOUNDS.1 - self.x, BOUNDS.1 - self.y),
+            false => self,
+        }
+    }
+    fn from_algebraic(name: &str) -> Option<CoordinateSet> {
+        let mut chars = name.chars();
+        let file = match chars.next()? {
+            file @ 'a'..='h' => file as i32 - 'a' as i32,
+            _ => return None,
+        };
+        let rank = match chars.next()? {
+            rank @ '1'..='8' => rank as i32 - '1' as i32,
+            _ => return None,
+        };
+        if chars.next().is_some() {
+            return None;
+        }
+        Some(CoordinateSet::from_file_rank(file, rank))
+    }
+    fn to_algebraic(self) -> String {
+        let (file, rank) = self.file_rank();
+        format!("{}{}", (b'a' + file as u8) as char, rank + 1)
+    }
 }
 impl std::ops::Add<&Direction> for CoordinateSet {
     type Output = CoordinateSet;
@@ -128,7 +187,7 @@ impl std::ops::Mul<i32> for &Direction {
 }
 
 use MoveType::*;
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 struct Piece {
     color: PieceColor,
     piece_type: PieceType,
@@ -136,12 +195,19 @@ struct Piece {
 #[derive(Clone, Debug)]
 struct BoardPosition {
     board: Board,
+    /// Mirrors board, set_piece keeps the two in sync ///
+    bitboards: Bitboards,
     en_passante: Option<CoordinateSet>,
-    children: Vec<BoardPosition>,
-    base_white_eval: i32,
-    tree_eval: i32,
+    side_to_move: PieceColor,
+    /// Moves since the last capture or pawn move ///
+    halfmove_clock: u32,
+    fullmove_number: u32,
+    /// Zobrist hash of the position, set_piece and make_move keep it up to date ///
+    hash: u64,
+    /// Hash of every earlier position in the game, oldest first, to spot repetitions ///
+    position_hashes: Vec<u64>,
 }
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 enum MoveType {
     Standard,
     CaptureOnly,
@@ -155,6 +221,94 @@ enum MoveType {
 }
 #[derive(Debug)]
 struct ChessMove(MoveType, Direction);
+/// A single move that can be made and unmade on a BoardPosition. kind is always one of
+/// NoCapture, CaptureOnly, PawnFirst, EnPassante, Castle, Promotion or PromotionCapture ///
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+struct Move {
+    from: CoordinateSet,
+    to: CoordinateSet,
+    promotion: Option<PieceType>,
+    kind: MoveType,
+}
+impl Move {
+    fn is_capture(&self) -> bool {
+        matches!(self.kind, CaptureOnly | EnPassante | PromotionCapture)
+    }
+    /// Captures and promotions, the moves quiescence search keeps looking at ///
+    fn is_tactical(&self) -> bool {
+        self.is_capture() || self.kind == Promotion
+    }
+}
+/// Long algebraic notation, e.g. e2e4 or e7e8q ///
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.from.to_algebraic(), self.to.to_algebraic())?;
+        match self.promotion {
+            Some(Knight) => write!(f, "n"),
+            Some(Bishop) => write!(f, "b"),
+            Some(Rook { .. }) => write!(f, "r"),
+            Some(Queen) => write!(f, "q"),
+            _ => Ok(()),
+        }
+    }
+}
+/// What make_move needs to put the position back ///
+#[derive(Debug, Clone, Copy)]
+struct Undo {
+    moved: Piece,
+    captured: Piece,
+    en_passante: Option<CoordinateSet>,
+    halfmove_clock: u32,
+    hash: u64,
+}
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum DrawReason {
+    Stalemate,
+    Repetition,
+    FiftyMoves,
+    InsufficientMaterial,
+}
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum WinReason {
+    Checkmate,
+    Resignation,
+}
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum GameResult {
+    WhiteWins(WinReason),
+    BlackWins(WinReason),
+    Draw(DrawReason),
+}
+impl GameResult {
+    fn win_for(color: PieceColor, reason: WinReason) -> GameResult {
+        match color {
+            PieceColor::White => GameResult::WhiteWins(reason),
+            PieceColor::Black => GameResult::BlackWins(reason),
+        }
+    }
+}
+impl fmt::Display for WinReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WinReason::Checkmate => write!(f, "checkmate"),
+            WinReason::Resignation => write!(f, "resignation"),
+        }
+    }
+}
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameResult::WhiteWins(reason) => write!(f, "White wins by {}", reason),
+            GameResult::BlackWins(reason) => write!(f, "Black wins by {}", reason),
+            GameResult::Draw(DrawReason::Stalemate) => write!(f, "Draw by stalemate"),
+            GameResult::Draw(DrawReason::Repetition) => write!(f, "Draw by threefold repetition"),
+            GameResult::Draw(DrawReason::FiftyMoves) => write!(f, "Draw by the fifty-move rule"),
+            GameResult::Draw(DrawReason::InsufficientMaterial) => {
+                write!(f, "Draw by insufficient material")
+            }
+        }
+    }
+}
 impl Piece {
     fn new(color: PieceColor, piece_type: PieceType) -> Piece {
         Piece { color, piece_type }
@@ -180,10 +334,9 @@ impl Piece {
         self.piece_type == Empty
     }
     fn forward(&self) -> i32 {
-        if self.color == BOTTOM_SIDE {
-            -1
-        } else {
-            1
+        match self.color {
+            PieceColor::White => -1,
+            PieceColor::Black => 1,
         }
     }
     fn set_moved(&mut self) {
@@ -347,32 +500,36 @@ impl fmt::Display for Piece {
 }
 impl Default for BoardPosition {
     fn default() -> Self {
-        BoardPosition {
-            board: INITIAL_BOARD.map(|row| row.map(|cell| Piece::new(cell.0, cell.1))),
-            tree_eval: 0,
+        let board = INITIAL_BOARD.map(|row| row.map(|cell| Piece::new(cell.0, cell.1)));
+        let mut position = BoardPosition {
+            board,
+            bitboards: Bitboards::from_board(&board),
             en_passante: None,
-            base_white_eval: 0,
-            children: Vec::new(),
-        }
+            side_to_move: PieceColor::White,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            hash: 0,
+            position_hashes: Vec::new(),
+        };
+        position.hash = position.zobrist_hash();
+        position
     }
 }
 impl From<[[(PieceColor, PieceType); 8]; 8]> for BoardPosition {
     fn from(item: [[(PieceColor, PieceType); 8]; 8]) -> BoardPosition {
-        BoardPosition {
-            board: item.map(|row| row.map(|cell| Piece::new(cell.0, cell.1))),
+        let board = item.map(|row| row.map(|cell| Piece::new(cell.0, cell.1)));
+        let mut position = BoardPosition {
+            board,
+            bitboards: Bitboards::from_board(&board),
             ..Default::default()
-        }
+        };
+        position.hash = position.zobrist_hash();
+        position
     }
 }
 type Board = [[Piece; 8]; 8];
 
 impl BoardPosition {
-    fn new(board: Board) -> BoardPosition {
-        BoardPosition {
-            board,
-            ..Default::default()
-        }
-    }
     fn get_piece(&self, square: &CoordinateSet) -> &Piece {
         debug_assert!(
             !square.out_of_bounds(),
@@ -387,6 +544,11 @@ impl BoardPosition {
             "set_piece out of bounds: {:?}",
             square
         );
+        let index = bitboard::square_index(square);
+        let old = self.board[square.y as usize][square.x as usize];
+        self.bitboards.toggle(index, &old);
+        self.bitboards.toggle(index, &piece);
+        self.hash ^= zobrist::piece_key(index, &old) ^ zobrist::piece_key(index, &piece);
         self.board[square.y as usize][square.x as usize] = piece;
     }
     fn clear_square(&mut self, square: &CoordinateSet) {
@@ -397,118 +559,239 @@ impl BoardPosition {
         );
         self.set_piece(square, Piece::new(PieceColor::Black, Empty));
     }
-    fn append_child(&mut self, mut new_child: BoardPosition) {
-        new_child.base_white_eval = new_child.eval(PieceColor::White);
-        self.children.push(new_child);
+    /// Adds to_add to moves unless it leaves the mover's own king in check ///
+    fn push_if_legal(&mut self, moves: &mut Vec<Move>, to_add: Move) {
+        let color_moving = self.side_to_move;
+        let undo = self.make_move(&to_add);
+        let legal = !self.is_in_check(color_moving);
+        self.unmake_move(&to_add, undo);
+        if legal {
+            moves.push(to_add);
+        }
     }
-    fn eval(&self, color_moving: PieceColor) -> i32 {
-        self.board.iter().fold(0, |acc, row| {
-            acc + row.iter().fold(0, |acc, piece| {
-                if piece.color == color_moving {
-                    acc + piece.point_value()
-                } else {
-                    acc - piece.point_value()
-                }
-            })
-        })
+    fn is_in_check(&self, color: PieceColor) -> bool {
+        self.bitboards
+            .king_square(color)
+            .is_some_and(|index| self.bitboards.is_attacked(index, !color))
     }
-    /// How good the position is for color_moving (higher is better) ///
-    fn tree_eval(&self, color_moving: PieceColor) -> i32 {
-        if self.children.is_empty() {
-            return match color_moving {
-                PieceColor::White => self.base_white_eval,
-                PieceColor::Black => -self.base_white_eval,
-            };
+    /// Whether any piece of color attacking_color could capture on square ///
+    fn is_square_attacked(&self, square: &CoordinateSet, attacking_color: PieceColor) -> bool {
+        self.bitboards
+            .is_attacked(bitboard::square_index(square), attacking_color)
+    }
+    /// Rook start and end squares for a king castling from king_from to king_to ///
+    fn castle_rook_squares(
+        king_from: &CoordinateSet,
+        king_to: &CoordinateSet,
+    ) -> (CoordinateSet, CoordinateSet) {
+        match king_to.x - king_from.x {
+            // Remember king is at position x=4
+            2 => (
+                king_from + Direction { x: 3, y: 0 },
+                king_from + Direction { x: 1, y: 0 },
+            ),
+            -2 => (
+                king_from + Direction { x: -4, y: 0 },
+                king_from + Direction { x: -1, y: 0 },
+            ),
+            _ => {
+                panic!("Bad Castling Direction ");
+            }
         }
-        // Best case scenario for moving is worst case for adversary, so minimum and then flip
-        -self.children.iter().fold(1000000, |acc, position| {
-            min(position.tree_eval(!color_moving), acc)
-        })
     }
 
-    fn move_arbitrary(&self, start: &CoordinateSet, end: &CoordinateSet) -> BoardPosition {
-        debug_assert!(
-            !start.out_of_bounds(),
-            "start in move_arbitrary out of bounds: {:?}",
-            start
-        );
+    fn make_move(&mut self, chess_move: &Move) -> Undo {
         debug_assert!(
-            !end.out_of_bounds(),
-            "end in move_arbitrary out of bounds: {:?}",
-            end
+            !chess_move.from.out_of_bounds() && !chess_move.to.out_of_bounds(),
+            "make_move out of bounds: {:?}",
+            chess_move
         );
-        let mut new_board = BoardPosition::new(self.board);
-        let mut new_piece = *self.get_piece(start);
+        let moved = *self.get_piece(&chess_move.from);
+        let captured_square = match chess_move.kind {
+            EnPassante => self
+                .en_passante
+                .expect("En passante move without an en passante pawn"),
+            _ => chess_move.to,
+        };
+        let undo = Undo {
+            moved,
+            captured: *self.get_piece(&captured_square),
+            en_passante: self.en_passante,
+            halfmove_clock: self.halfmove_clock,
+            hash: self.hash,
+        };
+
+        self.position_hashes.push(self.hash);
+        self.hash ^= self.state_key();
+
+        let mut new_piece = moved;
         new_piece.set_moved();
-        new_board.set_piece(end, new_piece);
-        new_board.clear_square(start);
-        new_board
+        if let Some(piece_type) = chess_move.promotion {
+            new_piece.piece_type = piece_type;
+        }
+        self.clear_square(&captured_square);
+        self.clear_square(&chess_move.from);
+        self.set_piece(&chess_move.to, new_piece);
+        if chess_move.kind == Castle {
+            let (rook_from, rook_to) =
+                BoardPosition::castle_rook_squares(&chess_move.from, &chess_move.to);
+            let mut rook = *self.get_piece(&rook_from);
+            rook.set_moved();
+            self.clear_square(&rook_from);
+            self.set_piece(&rook_to, rook);
+        }
+
+        self.en_passante = match chess_move.kind {
+            PawnFirst => Some(chess_move.to),
+            _ => None,
+        };
+        self.halfmove_clock = if moved.piece_type == Pawn || chess_move.is_capture() {
+            0
+        } else {
+            self.halfmove_clock + 1
+        };
+        if self.side_to_move == PieceColor::Black {
+            self.fullmove_number += 1;
+        }
+        self.side_to_move = !self.side_to_move;
+        self.hash ^= self.state_key();
+        undo
+    }
+
+    /// Passes the turn without moving, for null-move pruning. Only allowed when not in check ///
+    fn make_null_move(&mut self) -> Undo {
+        let empty = Piece::new(PieceColor::Black, Empty);
+        let undo = Undo {
+            moved: empty,
+            captured: empty,
+            en_passante: self.en_passante,
+            halfmove_clock: self.halfmove_clock,
+            hash: self.hash,
+        };
+        self.position_hashes.push(self.hash);
+        self.hash ^= self.state_key();
+        self.en_passante = None;
+        // Passing isn't a real move, so nothing before it counts as a repetition
+        self.halfmove_clock = 0;
+        self.side_to_move = !self.side_to_move;
+        self.hash ^= self.state_key();
+        undo
+    }
+
+    fn unmake_null_move(&mut self, undo: Undo) {
+        self.position_hashes.pop();
+        self.side_to_move = !self.side_to_move;
+        self.en_passante = undo.en_passante;
+        self.halfmove_clock = undo.halfmove_clock;
+        self.hash = undo.hash;
+    }
+
+    fn unmake_move(&mut self, chess_move: &Move, undo: Undo) {
+        self.position_hashes.pop();
+        self.side_to_move = !self.side_to_move;
+        if self.side_to_move == PieceColor::Black {
+            self.fullmove_number -= 1;
+        }
+        self.halfmove_clock = undo.halfmove_clock;
+        self.en_passante = undo.en_passante;
+
+        if chess_move.kind == Castle {
+            let (rook_from, rook_to) =
+                BoardPosition::castle_rook_squares(&chess_move.from, &chess_move.to);
+            let rook = *self.get_piece(&rook_to);
+            self.clear_square(&rook_to);
+            self.set_piece(
+                &rook_from,
+                Piece::new(rook.color, Rook { has_moved: false }),
+            );
+        }
+        self.clear_square(&chess_move.to);
+        self.set_piece(&chess_move.from, undo.moved);
+        let captured_square = match chess_move.kind {
+            EnPassante => undo
+                .en_passante
+                .expect("En passante move without an en passante pawn"),
+            _ => chess_move.to,
+        };
+        self.set_piece(&captured_square, undo.captured);
+        self.hash = undo.hash;
     }
 
-    fn move_repeat(&mut self, start: &CoordinateSet, direction: &Direction, repeat: i32) {
-        let piece = self.get_piece(start);
+    fn move_repeat(
+        &mut self,
+        start: &CoordinateSet,
+        direction: &Direction,
+        repeat: i32,
+        moves: &mut Vec<Move>,
+    ) {
+        let piece = *self.get_piece(start);
         let destination = start + direction * repeat;
         if destination.out_of_bounds() {
             return;
         }
-        let target = self.get_piece(&destination);
-        if (!target.is_empty() && target.color == piece.color) || destination.out_of_bounds() {
+        let target = *self.get_piece(&destination);
+        if !target.is_empty() && target.color == piece.color {
             return;
         }
-        let to_add = self.move_arbitrary(start, &destination);
-        self.append_child(to_add);
-        if self.get_piece(&destination).piece_type != Empty {
+        let to_add = Move {
+            from: *start,
+            to: destination,
+            promotion: None,
+            kind: if target.is_empty() {
+                NoCapture
+            } else {
+                CaptureOnly
+            },
+        };
+        self.push_if_legal(moves, to_add);
+        if !target.is_empty() {
             return;
         }
-        self.move_repeat(start, direction, repeat + 1)
+        self.move_repeat(start, direction, repeat + 1, moves)
     }
 
-    // fn push_if_exists(&mut self, to_add: Option<BoardPosition>) {
-    //     match to_add {
-    //         None => (),
-    //         Some(position) => {
-    //             self.append_child(position);
-    //         }
-    //     }
-    // }
-    fn eval_move(&mut self, coords: &CoordinateSet, move_to_eval: &ChessMove) {
-        // dbg!(move_to_eval);
-        let piece = self.get_piece(coords);
+    fn eval_move(
+        &mut self,
+        coords: &CoordinateSet,
+        move_to_eval: &ChessMove,
+        moves: &mut Vec<Move>,
+    ) {
+        let piece = *self.get_piece(coords);
         let destination = coords + &move_to_eval.1;
         if destination.out_of_bounds() {
             return;
         }
-        let target = self.get_piece(&destination);
+        let target = *self.get_piece(&destination);
         if target.is_empty() {
             if let CaptureOnly | PromotionCapture = move_to_eval.0 {
                 return;
             }
         } else {
-            if let EnPassante | NoCapture | PawnFirst | Promotion = move_to_eval.0 {
+            if let EnPassante | NoCapture | PawnFirst | Promotion | Castle = move_to_eval.0 {
                 return;
             }
             if target.color == piece.color {
                 return;
             }
         }
+        let simple_move = |kind| Move {
+            from: *coords,
+            to: destination,
+            promotion: None,
+            kind,
+        };
         // Guarantees going into match: destination is in bounds and capture rules are checked
         match move_to_eval.0 {
             EnPassante => match &self.en_passante {
                 None => (),
                 Some(enp_coords) => {
-                    let mut target_pawn = destination.clone();
+                    let mut target_pawn = destination;
                     target_pawn.y -= piece.forward();
                     // Make sure target is available for en passante
                     if *enp_coords != target_pawn {
                         return;
                     }
 
-                    // This should be guaranteed by capture checking
-                    debug_assert!(
-                        self.get_piece(&destination).is_empty(),
-                        "BAD En Passante Target Is Not Empty at {:?}",
-                        enp_coords
-                    );
                     // Should be guaranteed, but make sure
                     debug_assert!(
                         *self.get_piece(&target_pawn)
@@ -526,17 +809,13 @@ impl BoardPosition {
                         *self.get_piece(&target_pawn),
                         self
                     );
-                    let mut board = self.move_arbitrary(coords, &destination);
-                    board.set_piece(coords, *piece);
-                    board.clear_square(&target_pawn);
-                    board.clear_square(coords);
-                    self.append_child(board);
+                    self.push_if_legal(moves, simple_move(EnPassante));
                 }
             },
             PawnFirst => {
-                let row = match piece.color == BOTTOM_SIDE {
-                    true => 6,
-                    false => 1,
+                let row = match piece.color {
+                    PieceColor::White => 6,
+                    PieceColor::Black => 1,
                 };
                 if coords.y != row {
                     return;
@@ -550,14 +829,23 @@ impl BoardPosition {
                 {
                     return;
                 } // Not moving through other piece
-                let mut board = self.move_arbitrary(coords, &destination);
-                board.en_passante = Some(destination);
-                self.append_child(board);
+                self.push_if_legal(moves, simple_move(PawnFirst));
             }
             Standard | CaptureOnly | NoCapture => {
-                self.append_child(self.move_arbitrary(coords, &destination))
+                // Pawns reaching the last row must promote, which Promotion covers
+                if piece.piece_type == Pawn
+                    && (destination.y == BOUNDS.0 || destination.y == BOUNDS.1)
+                {
+                    return;
+                }
+                let kind = if target.is_empty() {
+                    NoCapture
+                } else {
+                    CaptureOnly
+                };
+                self.push_if_legal(moves, simple_move(kind))
             }
-            Repeat => self.move_repeat(coords, &move_to_eval.1, 1),
+            Repeat => self.move_repeat(coords, &move_to_eval.1, 1, moves),
             // May want to consider pulling some of this into another function
             Castle => {
                 match piece.piece_type {
@@ -570,20 +858,8 @@ impl BoardPosition {
                         panic!("Non King Castling Attempted");
                     }
                 };
-                let (rook_pos, mid_point) = match move_to_eval.1.x {
-                    // Remember king is at position x=4
-                    2 => (
-                        coords + Direction { x: 3, y: 0 },
-                        coords + Direction { x: 1, y: 0 },
-                    ),
-                    -2 => (
-                        coords + Direction { x: -4, y: 0 },
-                        coords + Direction { x: -1, y: 0 },
-                    ),
-                    _ => {
-                        panic!("Bad Castling Direction ");
-                    }
-                };
+                let (rook_pos, mid_point) =
+                    BoardPosition::castle_rook_squares(coords, &destination);
 
                 debug_assert!(
                     !rook_pos.out_of_bounds(),
@@ -597,158 +873,197 @@ impl BoardPosition {
                     // Don't need to check color because of has_moved
                     return;
                 }
-                if !self.get_piece(&mid_point).is_empty() {
-                    return;
-                }
-                //Don't need to check second space because it is destination
-                let mut test_board = self.move_arbitrary(coords, &mid_point);
-                // This method is a little iffy, because it will evaluate moves on the
-                // other side, including castling, which could then evaluate white moves,
-                // but that should stop after 2 because of has_moved, so it should be fine.
-                test_board.eval_moves(!piece.color);
-                for board in test_board.children {
-                    if *board.get_piece(&mid_point)
-                        != (Piece {
-                            piece_type: King { has_moved: true },
-                            color: piece.color,
-                        })
-                    {
+                // Every square between king and rook must be empty, the destination
+                // is already guaranteed by capture checking
+                let step = Direction {
+                    x: move_to_eval.1.x.signum(),
+                    y: 0,
+                };
+                let mut between = coords + &step;
+                while between != rook_pos {
+                    if !self.get_piece(&between).is_empty() {
                         return;
                     }
+                    between = between + &step;
+                }
+                // Can't castle out of or through check, moving into check is
+                // handled by push_if_legal
+                if self.is_square_attacked(coords, !piece.color)
+                    || self.is_square_attacked(&mid_point, !piece.color)
+                {
+                    return;
                 }
-                let mut final_board = self.move_arbitrary(coords, &destination);
-                final_board.set_piece(&mid_point, *rook);
-                final_board.clear_square(&rook_pos);
-                self.append_child(final_board);
+                self.push_if_legal(moves, simple_move(Castle));
             }
 
             Promotion | PromotionCapture => {
-                let row = match piece.color == BOTTOM_SIDE {
-                    true => 1,
-                    false => 6,
+                let row = match piece.color {
+                    PieceColor::White => 1,
+                    PieceColor::Black => 6,
                 };
                 if coords.y != row {
                     return;
                 }
-                let color = piece.color;
                 for piece_type in PieceType::to_promote() {
-                    let mut new_board = BoardPosition::new(self.board);
-                    new_board.clear_square(coords);
-                    new_board.set_piece(&destination, Piece { piece_type, color });
-                    self.append_child(new_board);
+                    self.push_if_legal(
+                        moves,
+                        Move {
+                            promotion: Some(piece_type),
+                            ..simple_move(move_to_eval.0)
+                        },
+                    );
                 }
             }
         }
     }
-    fn eval_moves(&mut self, player_color: PieceColor) {
-        for i in 0..self.board.len() {
-            for j in 0..self.board[i].len() {
-                let target = CoordinateSet {
-                    x: j as i32,
-                    y: i as i32,
-                };
-                let piece = self.get_piece(&target);
-                if piece.color != player_color || piece.piece_type == Empty {
+    /// All legal moves for the side to move ///
+    fn eval_moves(&mut self) -> Vec<Move> {
+        let mut moves = Vec::new();
+        let own = self.bitboards.color(self.side_to_move);
+        let occupied = self.bitboards.occupied();
+        for from_index in Bits(own) {
+            let from = bitboard::index_square(from_index);
+            let piece = *self.get_piece(&from);
+            let attacks = match piece.piece_type {
+                // Pawns and castling have too many special cases for plain attack sets
+                Pawn => {
+                    for move_to_eval in piece.get_moves() {
+                        self.eval_move(&from, &move_to_eval, &mut moves);
+                    }
                     continue;
                 }
-                self.board[i][j]
-                    .get_moves()
-                    .iter()
-                    .for_each(|move_to_eval| self.eval_move(&target, move_to_eval));
+                King { has_moved } => {
+                    if !has_moved {
+                        for move_to_eval in piece.get_moves() {
+                            if move_to_eval.0 == Castle {
+                                self.eval_move(&from, &move_to_eval, &mut moves);
+                            }
+                        }
+                    }
+                    bitboard::king_attacks(from_index)
+                }
+                Knight => bitboard::knight_attacks(from_index),
+                Bishop => bitboard::bishop_attacks(from_index, occupied),
+                Rook { .. } => bitboard::rook_attacks(from_index, occupied),
+                Queen => {
+                    bitboard::bishop_attacks(from_index, occupied)
+                        | bitboard::rook_attacks(from_index, occupied)
+                }
+                Empty => 0,
+            };
+            for to_index in Bits(attacks & !own) {
+                let kind = if occupied & (1 << to_index) == 0 {
+                    NoCapture
+                } else {
+                    CaptureOnly
+                };
+                self.push_if_legal(
+                    &mut moves,
+                    Move {
+                        from,
+                        to: bitboard::index_square(to_index),
+                        promotion: None,
+                        kind,
+                    },
+                );
             }
         }
+        moves
     }
 
-    fn get_legal_moves_piece(&mut self, target: &CoordinateSet) -> Vec<ChessMove> {
+    fn get_legal_moves_piece(&mut self, target: &CoordinateSet) -> Vec<Move> {
         debug_assert!(!target.out_of_bounds());
-        let mut moves: Vec<ChessMove> = Vec::new();
-        let piece = self.get_piece(target);
-        debug_assert!(piece.piece_type != Empty);
+        debug_assert!(self.get_piece(target).piece_type != Empty);
+        let mut moves = self.eval_moves();
+        moves.retain(|chess_move| chess_move.from == *target);
+        moves
+    }
 
-        let potential_moves = self.get_piece(target).get_moves();
-        for potential_move in potential_moves {
-            let old_length = self.children.len();
-            self.eval_move(target, &potential_move);
-            if self.children.len() > old_length {
-                if potential_move.0 == Repeat {
-                    for i in old_length..self.children.len() {
-                        moves.push(ChessMove(
-                            Standard,
-                            &potential_move.1 * (i - old_length + 1) as i32,
-                        ));
-                    }
+    /// The board for a terminal with ranks and files labelled, highlighted squares are green ///
+    fn board_string(&self, flipped: bool, highlighted: &[CoordinateSet]) -> String {
+        let files: String = (0..BOARD_SIZE)
+            .map(|i| {
+                let (file, _) = CoordinateSet::new(i, 0).oriented(flipped).file_rank();
+                format!(" {} ", (b'a' + file as u8) as char)
+            })
+            .collect();
+        let mut board_string = format!("  {}\n", files);
+        for j in 0..BOARD_SIZE {
+            let (_, rank) = CoordinateSet::new(0, j).oriented(flipped).file_rank();
+            board_string.push_str(&format!("{} ", rank + 1));
+            for i in 0..BOARD_SIZE {
+                let square = CoordinateSet::new(i, j).oriented(flipped);
+                let background = if highlighted.contains(&square) {
+                    "\x1b[42m"
+                } else if (square.x + square.y) % 2 == 0 {
+                    "\x1b[40m"
                 } else {
-                    moves.push(potential_move);
-                }
+                    "\x1b[41m"
+                };
+                board_string.push_str(&format!(
+                    "{} {} ",
+                    background,
+                    self.get_piece(&square).character()
+                ));
             }
+            board_string.push_str(&format!("\x1b[0m {}\n", rank + 1));
         }
-        moves
+        board_string.push_str(&format!("  {}", files));
+        board_string
     }
 
-    fn _display_with_moves(&mut self, piece: &CoordinateSet) {
+    fn display_with_moves(&mut self, piece: &CoordinateSet, flipped: bool) {
         debug_assert!(
             !piece.out_of_bounds(),
             "Error: {:?} is out of bounds in display_with_moves",
             piece
         );
-        let moves = self.get_legal_moves_piece(piece);
-        let mut move_squares = [[0; 8]; 8];
-        moves.into_iter().for_each(|chess_move| {
-            let coords = piece.clone() + chess_move.1;
-            move_squares[coords.y as usize][coords.x as usize] = 1;
-        });
-        let board_string = self
-            .board
+        let targets: Vec<CoordinateSet> = self
+            .get_legal_moves_piece(piece)
             .iter()
-            .enumerate()
-            .fold(String::new(), |str, (i, row)| {
-                format!(
-                    "{}{} {}\x1b[40m\n",
-                    str,
-                    i,
-                    row.iter()
-                        .enumerate()
-                        .fold(String::new(), |str, (j, piece)| {
-                            format!(
-                                "{}{} {} ",
-                                str,
-                                if move_squares[i][j] == 1 {
-                                    "\x1b[42m"
-                                } else if (j + i) % 2 == 0 {
-                                    "\x1b[40m"
-                                } else {
-                                    "\x1b[41m"
-                                },
-                                piece.character()
-                            )
-                        }),
-                )
-            });
-        println!(
-            "   a  b  c  d  e  f  g  h\n{}En Passante: {}",
-            board_string,
-            if self.en_passante.is_none() {
-                "No Eligible Pawns"
-            } else {
-                "Pawn Eligible"
-            }
-        )
+            .map(|chess_move| chess_move.to)
+            .collect();
+        println!("{}", self.board_string(flipped, &targets));
+    }
+    /// None while the game goes on ///
+    fn game_result(&self) -> Option<GameResult> {
+        if !self.clone().eval_moves().is_empty() {
+            self.draw_reason().map(GameResult::Draw)
+        } else if self.is_in_check(self.side_to_move) {
+            Some(GameResult::win_for(
+                !self.side_to_move,
+                WinReason::Checkmate,
+            ))
+        } else {
+            Some(GameResult::Draw(DrawReason::Stalemate))
+        }
+    }
+    /// True if moving from -> to is a legal promotion, so a piece has to be chosen ///
+    fn is_promotion(&mut self, from: CoordinateSet, to: CoordinateSet) -> bool {
+        self.get_legal_moves_piece(&from)
+            .iter()
+            .any(|chess_move| chess_move.to == to && chess_move.promotion.is_some())
     }
+    /// Makes the move from -> to if it is legal and returns it.
+    /// promotion is only looked at when the move is a promotion, and must be given then.
     fn move_piece(
         &mut self,
         from: CoordinateSet,
         to: CoordinateSet,
-    ) -> Result<BoardPosition, String> {
+        promotion: Option<PieceType>,
+    ) -> Result<Move, String> {
+        if promotion.is_none() && self.is_promotion(from, to) {
+            return Err(String::from("Choose a piece to promote to"));
+        }
         let moves = self.get_legal_moves_piece(&from);
         let potential_move = moves.into_iter().find(|chess_move| {
-            let coords = from.clone() + chess_move.1.clone();
-            coords == to
+            chess_move.to == to
+                && (chess_move.promotion.is_none() || chess_move.promotion == promotion)
         });
         match potential_move {
             Some(chosen_move) => {
-                self.children.clear();
-                self.eval_move(&from, &chosen_move);
-                Ok(self.children.remove(0))
+                self.make_move(&chosen_move);
+                Ok(chosen_move)
             }
             None => Err(String::from("Illegal Move, try again")),
         }
@@ -757,35 +1072,10 @@ impl BoardPosition {
 
 impl fmt::Display for BoardPosition {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let board_string = self
-            .board
-            .iter()
-            .enumerate()
-            .fold(String::new(), |str, (i, row)| {
-                format!(
-                    "{}{} {}\x1b[40m\n",
-                    str,
-                    i,
-                    row.iter()
-                        .enumerate()
-                        .fold(String::new(), |str, (j, piece)| {
-                            format!(
-                                "{}{} {} ",
-                                str,
-                                if (j + i) % 2 == 0 {
-                                    "\x1b[40m"
-                                } else {
-                                    "\x1b[41m"
-                                },
-                                piece.character()
-                            )
-                        }),
-                )
-            });
         write!(
             f,
-            "   a  b  c  d  e  f  g  h\n{}En Passante: {}",
-            board_string,
+            "{}\nEn Passante: {}",
+            self.board_string(false, &[]),
             if self.en_passante.is_none() {
                 "No Eligible Pawns"
             } else {
@@ -795,157 +1085,215 @@ impl fmt::Display for BoardPosition {
     }
 }
 
-fn expand_tree(
-    root: &mut BoardPosition,
-    progress: &mut Vec<usize>,
-    min_depth: usize,
-    max_depth: usize,
-    initial_turn: PieceColor,
-) {
-    let depth = progress.len();
-    if depth > max_depth {
-        // This is so that the bot doesn't infinite loop if it's gone to max depth
-        println!("Bot surpassed maximum");
-        sleep(time::Duration::from_millis(100));
-        return;
-    }
-    let mut current: &mut BoardPosition = root;
-    if progress[min_depth] == 1 {
-        progress.fill(0);
-        progress.push(0);
-        return expand_tree(root, progress, min_depth, max_depth, initial_turn);
-    }
-    for i in min_depth + 1..progress.len() {
-        let p = progress[i];
-        debug_assert!(p <= current.children.len());
-
-        if current.children.len() == p {
-            progress[i] = 0;
-            progress[i - 1] += 1;
-            return expand_tree(root, progress, min_depth, max_depth, initial_turn);
-        }
-
-        current = &mut current.children[p];
-    }
-    current.eval_moves(if depth % 2 == 1 {
-        initial_turn
-    } else {
-        !initial_turn
-    });
-    progress[depth - 1] += 1;
-}
-
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 #[allow(dead_code)]
 enum MessageToBot {
     Stop,
-    Move(Board),
+    Move(Move),
+    /// Throws away the game so far and carries on from this position, used for takebacks ///
+    SetPosition(Box<BoardPosition>),
 }
 #[derive(Debug)]
 enum MessageToMain {
     Error(String),
-    Move(BoardPosition),
+    Move(Move),
+    /// Sent instead of Move once the game has ended, with the bot's final move if it made one ///
+    GameOver(GameResult, Option<Move>),
+    /// The line the bot expects, sent after every depth it finishes searching ///
+    Thinking(String),
+}
+
+/// A line like "Depth 6: +0.35 1. e4 e5 2. Nf3" with the score from White's point of view ///
+fn thinking_line(root: &BoardPosition, result: &SearchResult) -> String {
+    let white_view = match root.side_to_move {
+        PieceColor::White => 1,
+        PieceColor::Black => -1,
+    };
+    let score = match result.mate_in() {
+        Some(moves) => format!("#{}", moves * white_view),
+        None => format!("{:+.2}", (result.score * white_view) as f64 / 100.0),
+    };
+    let mut line = GameHistory::new(root.clone());
+    for chess_move in &result.pv {
+        line.push(*chess_move);
+    }
+    format!(
+        "Depth {}: {} {}",
+        result.depth,
+        score,
+        line.numbered_moves().join(" ")
+    )
+}
+
+/// Once the game is over the bot waits to be stopped, or to be taken back to an earlier position ///
+fn wait_after_game_over(
+    bot_out: &Sender<MessageToMain>,
+    bot_in: &Receiver<MessageToBot>,
+) -> Option<BoardPosition> {
+    loop {
+        match bot_in.recv() {
+            Ok(MessageToBot::Move(_)) => {
+                bot_out
+                    .send(MessageToMain::Error(String::from("Game is already over")))
+                    .unwrap();
+            }
+            Ok(MessageToBot::SetPosition(position)) => return Some(*position),
+            Ok(MessageToBot::Stop) | Err(_) => return None,
+        }
+    }
 }
 
 fn run_bot(
     bot_out: Sender<MessageToMain>,
     bot_in: Receiver<MessageToBot>,
-    initial_position: Board,
+    initial_position: BoardPosition,
     bot_color: PieceColor,
-    initial_turn: PieceColor,
+    mut time_control: TimeControl,
+    options: SearchOptions,
 ) {
-    let mut position = BoardPosition::new(initial_position);
-    let min_depth = 0;
-    let max_depth = 5;
-    let mut evaluated = 0;
-    let mut progress = vec![0];
-    let mut old = Instant::now();
+    let mut position = initial_position;
+    // Without a time limit fall back to a fixed depth so the bot still answers
+    let max_depth = match time_control.budget() {
+        Some(_) => MAX_DEPTH,
+        None => 5,
+    };
+    let tt = Arc::new(TranspositionTable::new(transposition::DEFAULT_SIZE_MB));
+    // Searching the position after the human's expected reply while they think
+    let mut ponder: Option<PonderSearch> = None;
     loop {
-        match bot_in.try_recv() {
-            Ok(message) => match message {
-                MessageToBot::Move(board) => {
-                    // let move_index = position.children.iter().position(|a| {
-                    //     a.board.iter().zip(board).fold(true||)
-                    // });
-                    let move_index = position.children.iter().position(|a| a.board == board);
-                    match move_index {
-                        None => {
-                            bot_out
-                                .send(MessageToMain::Error(String::from("Illegal Move")))
-                                .unwrap();
-                        }
-                        Some(index) => {
-                            position = position.children.remove(index);
-                            let current_progress = progress.remove(0);
-                            if current_progress != index {
-                                progress.fill(0);
-                            }
-                            position
-                                .children
-                                .iter_mut()
-                                .for_each(|child| child.tree_eval = child.tree_eval(!bot_color));
-
-                            let (move_correct, current_eval) = position
-                                .children
-                                .iter()
-                                .enumerate()
-                                .fold((0_usize, 100000), |current, new| {
-                                    if new.1.tree_eval < current.1 {
-                                        (new.0, new.1.tree_eval)
-                                    } else {
-                                        current
-                                    }
-                                });
-
-                            println!("Current Eval: {}", current_eval);
-                            position = position.children.swap_remove(move_correct);
-                            let current_progress = progress.remove(0);
-                            if current_progress != index {
-                                progress.fill(0);
-                            }
-                            bot_out
-                                .send(MessageToMain::Move(BoardPosition {
-                                    children: Vec::new(),
-                                    board: position.board,
-                                    en_passante: position.en_passante.clone(),
-                                    ..position
-                                }))
-                                .unwrap();
-                        }
-                    }
+        if position.side_to_move == bot_color {
+            // Only a game loaded as over gets here, the UI saw that itself and doesn't read
+            // from the bot until a takeback, when a GameOver would be stale
+            if position.game_result().is_some() {
+                match wait_after_game_over(&bot_out, &bot_in) {
+                    Some(restored) => position = restored,
+                    None => return,
                 }
-                MessageToBot::Stop => {
-                    return;
+                continue;
+            }
+            let start = Instant::now();
+            let root = position.clone();
+            // Nodes per second count from when the search began, before start after a ponderhit
+            let mut search_start = start;
+            let search_result = match ponder.take() {
+                // Ponderhit, this position has been searched since the bot's last move
+                Some(search) => {
+                    search_start = search.started;
+                    search.ponderhit()
                 }
-            },
-            Err(e) => match e {
-                mpsc::TryRecvError::Empty => {
-                    evaluated += 1;
-                    if evaluated % 100000 == 0 {
-                        let secs_taken = old.elapsed().as_secs_f64();
-                        println!(
-                            "Evaluated 100k moves in {} seconds ({} moves/second)",
-                            secs_taken,
-                            100000.0 / secs_taken
-                        );
-                        old = Instant::now();
-                    }
-                    expand_tree(
-                        &mut position,
-                        &mut progress,
-                        min_depth,
-                        max_depth,
-                        initial_turn,
-                    )
+                None => Searcher::new(
+                    Arc::new(AtomicBool::new(false)),
+                    time_control.budget(),
+                    &tt,
+                    options,
+                )
+                .iterative_deepening(&mut position, max_depth, &mut |result| {
+                    let _ = bot_out.send(MessageToMain::Thinking(thinking_line(&root, result)));
+                }),
+            }
+            .expect("game_result said there is a legal move");
+            time_control.spend(start.elapsed());
+            let secs_taken = search_start.elapsed().as_secs_f64();
+            println!(
+                "Current Eval: {:+.2} (depth {}, {} nodes in {} seconds, {} nodes/second)",
+                match bot_color {
+                    PieceColor::White => search_result.score,
+                    PieceColor::Black => -search_result.score,
+                } as f64
+                    / 100.0,
+                search_result.depth,
+                search_result.nodes,
+                secs_taken,
+                search_result.nodes as f64 / secs_taken
+            );
+            let best_move = search_result.pv[0];
+            position.make_move(&best_move);
+            if let Some(result) = position.game_result() {
+                bot_out
+                    .send(MessageToMain::GameOver(result, Some(best_move)))
+                    .unwrap();
+                match wait_after_game_over(&bot_out, &bot_in) {
+                    Some(restored) => position = restored,
+                    None => return,
                 }
-                mpsc::TryRecvError::Disconnected => {
-                    return;
+                continue;
+            }
+            bot_out.send(MessageToMain::Move(best_move)).unwrap();
+            ponder = start_pondering(
+                &position,
+                &search_result,
+                time_control.budget(),
+                max_depth,
+                &tt,
+                options,
+                &bot_out,
+            );
+        }
+        match bot_in.recv() {
+            Ok(MessageToBot::Move(chess_move)) => {
+                if !position.eval_moves().contains(&chess_move) {
+                    bot_out
+                        .send(MessageToMain::Error(String::from("Illegal Move")))
+                        .unwrap();
+                    continue;
                 }
-            },
+                // Ponder miss, dropping the search stops it but its table entries stay
+                if ponder
+                    .as_ref()
+                    .is_some_and(|search| search.predicted != chess_move)
+                {
+                    ponder = None;
+                }
+                position.make_move(&chess_move);
+                debug_assert_eq!(position.side_to_move, bot_color);
+                if let Some(result) = position.game_result() {
+                    bot_out.send(MessageToMain::GameOver(result, None)).unwrap();
+                    match wait_after_game_over(&bot_out, &bot_in) {
+                        Some(restored) => position = restored,
+                        None => return,
+                    }
+                }
+            }
+            Ok(MessageToBot::SetPosition(restored)) => {
+                ponder = None;
+                position = *restored;
+            }
+            Ok(MessageToBot::Stop) | Err(_) => return,
         }
     }
 }
 
+/// Starts searching the reply the bot's principal variation expects from the human,
+/// unless there is none or it would end the game ///
+fn start_pondering(
+    position: &BoardPosition,
+    search_result: &SearchResult,
+    budget: Option<TimeBudget>,
+    max_depth: u32,
+    tt: &Arc<TranspositionTable>,
+    options: SearchOptions,
+    bot_out: &Sender<MessageToMain>,
+) -> Option<PonderSearch> {
+    let predicted = *search_result.pv.get(1)?;
+    let mut root = position.clone();
+    root.make_move(&predicted);
+    if root.game_result().is_some() {
+        return None;
+    }
+    let bot_out = bot_out.clone();
+    Some(PonderSearch::start(
+        position,
+        predicted,
+        budget,
+        max_depth,
+        tt.clone(),
+        options,
+        move |result| {
+            let _ = bot_out.send(MessageToMain::Thinking(thinking_line(&root, result)));
+        },
+    ))
+}
+
 // const INITIAL_BOARD: [[(Color,PieceType); 8]; 8] = [
 //     [(PieceColor::Black,Empty),(PieceColor::Black,Empty),(PieceColor::Black,Empty),(PieceColor::Black,Empty),(PieceColor::Black,King { has_moved: false}),(PieceColor::Black,Empty),(PieceColor::Black,Empty),(PieceColor::Black,Empty)],
 //     [(PieceColor::White,Pawn),(PieceColor::Black,Pawn),(PieceColor::Black,Pawn),(PieceColor::Black,Pawn),(PieceColor::Black,Pawn),(PieceColor::Black,Pawn),(PieceColor::Black,Pawn),(PieceColor::Black,Pawn)],
@@ -956,108 +1304,105 @@ fn run_bot(
 //     [(PieceColor::Black,Empty),(PieceColor::Black,Empty),(PieceColor::Black,Empty),(PieceColor::Black,Empty),(PieceColor::Black,Empty),(PieceColor::Black,Empty),(PieceColor::Black,Empty),(PieceColor::Black,Empty)],
 //     [(PieceColor::White,Rook { has_moved: false }),(PieceColor::Black,Empty),(PieceColor::Black,Empty),(PieceColor::White,Empty),(PieceColor::White,King { has_moved: false }),(PieceColor::White,Empty),(PieceColor::White,Empty),(PieceColor::White,Rook { has_moved: false })],
 // ];
-fn _convert_notation_to_coords(notation: String) -> Result<CoordinateSet, String> {
-    let notation = String::from(notation.trim());
-    let mut chars = notation.chars();
-
-    let x_chars = ['a', 'b', 'c', 'd', 'e', 'f', 'g', 'h'];
-    let x: i32 = match chars.next() {
-        Some(char) => match x_chars.iter().position(|&c| char == c) {
-            Some(index) => index as i32,
-            None => {
-                return Err(String::from("Invalid X Position"));
-            }
-        },
-        None => {
-            return Err(String::from("No Input"));
-        }
-    };
-
-    let y = match chars.next() {
-        Some(char) => match char.to_digit(10) {
-            Some(index) => index as i32,
-            None => {
-                return Err(String::from("Invalid Y Position"));
-            }
-        },
-        None => {
-            return Err(String::from("Invalid Y Position"));
-        }
-    };
-
-    Ok(CoordinateSet::new(x, y))
+/// Reads a square like e2 ///
+fn convert_notation_to_coords(notation: &str) -> Result<CoordinateSet, String> {
+    let notation = notation.trim();
+    if notation.is_empty() {
+        return Err(String::from("No Input"));
+    }
+    CoordinateSet::from_algebraic(notation)
+        .ok_or_else(|| format!("'{}' is not a square like e2", notation))
 }
-fn _command_line_ui(
+const TERMINAL_HELP: &str = "Enter a move like Nf3 or g1f3, or a square like g1 to see its moves.
+Commands: undo, fen, flip, resign, help, quit";
+fn command_line_ui(
     main_in: Receiver<MessageToMain>,
     main_out: Sender<MessageToBot>,
     player_color: PieceColor,
-    mut current_position: BoardPosition,
+    mut history: GameHistory,
+    save_path: String,
 ) {
+    let mut flipped = player_color == PieceColor::Black;
+    let mut game_result = history.current().game_result();
+    println!("{}", TERMINAL_HELP);
+    if game_result.is_none() && history.current().side_to_move != player_color {
+        println!("Rook Bot is thinking...");
+    }
     loop {
-        println!("{}", current_position);
-        println!("Please enter desired piece: ",);
-        let mut input = String::new();
-        stdin().read_line(&mut input).expect("Failed to read line");
-
-        let piece_coords = match _convert_notation_to_coords(input) {
-            Ok(num) => num,
-            Err(e) => {
-                println!("{}", e);
-                continue;
+        if game_result.is_none() && history.current().side_to_move != player_color {
+            match main_in.recv() {
+                Ok(MessageToMain::Thinking(line)) => println!("{}", line),
+                Ok(MessageToMain::Move(bot_move)) => history.push(bot_move),
+                Ok(MessageToMain::GameOver(result, bot_move)) => {
+                    if let Some(bot_move) = bot_move {
+                        history.push(bot_move);
+                    }
+                    save_game(&save_path, &history, player_color, result);
+                    game_result = Some(result);
+                }
+                Ok(MessageToMain::Error(e)) => {
+                    println!("Bot received ERROR:\n{}", e);
+                    break;
+                }
+                Err(e) => {
+                    println!("Bot failed to receive:\n{}", e);
+                    break;
+                }
             }
-        };
-
-        let piece = current_position.get_piece(&piece_coords);
-        if piece.piece_type == Empty || piece.color != player_color {
-            println!("You don't have a piece there");
             continue;
         }
-        current_position._display_with_moves(&piece_coords);
 
-        println!("Please enter desired move: ",);
-        input = String::new();
-        stdin().read_line(&mut input).expect("Failed to read line");
-        let target = match _convert_notation_to_coords(input) {
-            Ok(num) => num,
-            Err(e) => {
-                println!("{}", e);
-                continue;
-            }
-        };
+        let mut current_position = history.current().clone();
+        println!("\n{}", current_position.board_string(flipped, &[]));
+        if let Some(last_move) = history.sans().last() {
+            println!("Last move: {}", last_move);
+        }
+        match &game_result {
+            Some(result) => println!("{}, undo to take back or quit to leave", result),
+            None => println!("{:?} to move", current_position.side_to_move),
+        }
+        let mut input = String::new();
+        if stdin().read_line(&mut input).expect("Failed to read line") == 0 {
+            break;
+        }
 
-        let moves = current_position.get_legal_moves_piece(&piece_coords);
-        let potential_move = moves.into_iter().find(|chess_move| {
-            let coords = piece_coords.clone() + chess_move.1.clone();
-            coords == target
-        });
-        match potential_move {
-            Some(chosen_move) => {
-                current_position.children.clear();
-                current_position.eval_move(&piece_coords, &chosen_move);
-                current_position = current_position.children.remove(0);
-                main_out
-                    .send(MessageToBot::Move(current_position.board))
-                    .unwrap();
-            }
-            None => {
-                println!("Illegal Move, try again");
-                continue;
+        match input.trim() {
+            "undo" => match history.takeback(player_color) {
+                Some(restored) => {
+                    game_result = None;
+                    main_out
+                        .send(MessageToBot::SetPosition(Box::new(restored.clone())))
+                        .unwrap();
+                }
+                None => println!("There is no move of yours to take back"),
+            },
+            "fen" => println!("{}", current_position.to_fen()),
+            "flip" => flipped = !flipped,
+            "resign" if game_result.is_none() => {
+                let result = GameResult::win_for(!player_color, WinReason::Resignation);
+                save_game(&save_path, &history, player_color, result);
+                game_result = Some(result);
             }
-        }
-        let incoming = main_in.recv();
-        match incoming {
-            Ok(message) => match message {
-                MessageToMain::Move(new_position) => {
-                    current_position = new_position;
+            "help" => println!("{}", TERMINAL_HELP),
+            "quit" => break,
+            _ if game_result.is_some() => println!("The game is over"),
+            text => {
+                // A square is also a pawn move in SAN, so it only shows moves if one of ours is there
+                if let Ok(square) = convert_notation_to_coords(text) {
+                    let piece = current_position.get_piece(&square);
+                    if piece.piece_type != Empty && piece.color == player_color {
+                        current_position.display_with_moves(&square, flipped);
+                        continue;
+                    }
                 }
-                MessageToMain::Error(e) => {
-                    println!("Bot received ERROR:\n{}", e);
-                    break;
+                match current_position.parse_move(text) {
+                    Ok(chosen_move) => {
+                        history.push(chosen_move);
+                        main_out.send(MessageToBot::Move(chosen_move)).unwrap();
+                        println!("Rook Bot is thinking...");
+                    }
+                    Err(e) => println!("{}", e),
                 }
-            },
-            Err(e) => {
-                println!("Bot failed to receive:\n{}", e);
-                break;
             }
         }
     }
@@ -1067,14 +1412,33 @@ fn _command_line_ui(
 use macroquad::prelude::*;
 const BOARD_SIZE: i32 = 8;
 const PADDING_SIZE: f32 = 50.0;
+/// The picker squares run from the promotion square towards the middle of the board, in screen squares ///
+fn promotion_choices(to: CoordinateSet) -> [(CoordinateSet, PieceType); 4] {
+    let direction = if to.y < BOARD_SIZE / 2 { 1 } else { -1 };
+    let pieces = PieceType::to_promote();
+    std::array::from_fn(|i| {
+        let square = CoordinateSet::new(to.x, to.y + direction * i as i32);
+        (square, pieces[i])
+    })
+}
 async fn graphical_ui(
     main_in: Receiver<MessageToMain>,
     main_out: Sender<MessageToBot>,
     player_color: PieceColor,
-    mut current_position: BoardPosition,
+    mut history: GameHistory,
+    save_path: String,
 ) {
+    let mut current_position = history.current().clone();
     let mut dragging_piece: Option<(CoordinateSet, Piece)> = None;
+    // A pawn dropped on the last rank waits here until a piece is picked
+    let mut pending_promotion: Option<(CoordinateSet, CoordinateSet)> = None;
+    // A loaded game may already be over
+    let mut game_result = current_position.game_result();
     let mut mouse_offset = vec2(0.0, 0.0);
+    // Start with the player's pieces at the bottom, F turns the board around
+    let mut flipped = player_color == PieceColor::Black;
+    // The bot's latest line, it stays up after the bot moves until its next search reports
+    let mut thinking: Option<String> = None;
     loop {
         let square_size: f32 = (min(screen_width() as i32, screen_height() as i32) as f32
             - PADDING_SIZE * 2.0)
@@ -1094,7 +1458,8 @@ async fn graphical_ui(
                     square_size,
                     color,
                 );
-                let piece = current_position.get_piece(&CoordinateSet::new(i, j));
+                let square = CoordinateSet::new(i, j).oriented(flipped);
+                let piece = current_position.get_piece(&square);
                 if piece.piece_type == Empty {
                     continue;
                 }
@@ -1102,7 +1467,7 @@ async fn graphical_ui(
                 base_path.push_str(&piece.image_file_name());
                 let texture = load_texture(&base_path).await.unwrap();
                 if let Some((drag_coord, _)) = &dragging_piece {
-                    if drag_coord.x == i && drag_coord.y == j {
+                    if *drag_coord == square {
                         continue;
                     }
                 }
@@ -1136,7 +1501,7 @@ async fn graphical_ui(
             let moves = current_position.get_legal_moves_piece(from);
             let circle_color = color_u8!(100, 100, 100, 100);
             for chess_move in moves {
-                let target = from + chess_move.1;
+                let target = chess_move.to.oriented(flipped);
                 draw_circle(
                     (target.x as f32 + 0.5) * square_size + PADDING_SIZE,
                     (target.y as f32 + 0.5) * square_size + PADDING_SIZE,
@@ -1145,14 +1510,101 @@ async fn graphical_ui(
                 );
             }
         }
-        if is_mouse_button_pressed(MouseButton::Left) {
+        if let Some(result) = &game_result {
+            draw_text(
+                &result.to_string(),
+                PADDING_SIZE,
+                PADDING_SIZE * 0.7,
+                PADDING_SIZE * 0.6,
+                BLACK,
+            );
+        }
+        if let Some(line) = &thinking {
+            draw_text(
+                line,
+                PADDING_SIZE,
+                PADDING_SIZE * 1.7 + square_size * BOARD_SIZE as f32,
+                PADDING_SIZE * 0.5,
+                BLACK,
+            );
+        }
+        // The most recent moves that fit beside the board
+        let moves_x = PADDING_SIZE * 1.5 + square_size * BOARD_SIZE as f32;
+        let line_height = PADDING_SIZE * 0.5;
+        let max_lines = (square_size * BOARD_SIZE as f32 / line_height) as usize;
+        let move_lines = history.numbered_moves();
+        for (i, line) in move_lines
+            .iter()
+            .skip(move_lines.len().saturating_sub(max_lines))
+            .enumerate()
+        {
+            draw_text(
+                line,
+                moves_x,
+                PADDING_SIZE + line_height * (i as f32 + 0.7),
+                line_height * 0.8,
+                BLACK,
+            );
+        }
+        let mut human_move = None;
+        if let Some((from, to)) = pending_promotion {
+            // Dim the board so the picker stands out
+            draw_rectangle(
+                PADDING_SIZE,
+                PADDING_SIZE,
+                square_size * BOARD_SIZE as f32,
+                square_size * BOARD_SIZE as f32,
+                color_u8!(0, 0, 0, 120),
+            );
+            let choices = promotion_choices(to.oriented(flipped));
+            for (square, piece_type) in &choices {
+                draw_rectangle(
+                    square.x as f32 * square_size + PADDING_SIZE,
+                    square.y as f32 * square_size + PADDING_SIZE,
+                    square_size,
+                    square_size,
+                    WHITE,
+                );
+                let mut base_path = String::from("assets/");
+                base_path.push_str(&Piece::new(player_color, *piece_type).image_file_name());
+                let texture = load_texture(&base_path).await.unwrap();
+                draw_texture_ex(
+                    &texture,
+                    PADDING_SIZE + (square.x as f32 * square_size),
+                    PADDING_SIZE + (square.y as f32 * square_size),
+                    WHITE,
+                    DrawTextureParams {
+                        dest_size: Some(vec2(square_size, square_size)),
+                        ..Default::default()
+                    },
+                );
+            }
+            if is_mouse_button_pressed(MouseButton::Left) {
+                let mouse_position = mouse_position();
+                let i = ((mouse_position.0 - PADDING_SIZE) / square_size).floor() as i32;
+                let j = ((mouse_position.1 - PADDING_SIZE) / square_size).floor() as i32;
+                // Clicking anywhere other than a choice takes the pawn back
+                pending_promotion = None;
+                if let Some((_, piece_type)) = choices
+                    .iter()
+                    .find(|(square, _)| square.x == i && square.y == j)
+                {
+                    human_move = current_position
+                        .move_piece(from, to, Some(*piece_type))
+                        .ok();
+                }
+            }
+        } else if game_result.is_none() && is_mouse_button_pressed(MouseButton::Left) {
             let mouse_position = mouse_position();
             let i = ((mouse_position.0 - PADDING_SIZE) / square_size).floor() as i32;
             let j = ((mouse_position.1 - PADDING_SIZE) / square_size).floor() as i32;
             if (0..BOARD_SIZE).contains(&i) && (0..BOARD_SIZE).contains(&j) {
-                let coord = CoordinateSet::new(i, j);
+                let coord = CoordinateSet::new(i, j).oriented(flipped);
                 let piece = current_position.get_piece(&coord);
-                if piece.piece_type != Empty && piece.color == player_color {
+                if piece.piece_type != Empty
+                    && piece.color == player_color
+                    && current_position.side_to_move == player_color
+                {
                     dragging_piece = Some((coord, *piece));
                     mouse_offset = vec2(
                         (mouse_position.0 - PADDING_SIZE) % square_size - square_size / 2.0,
@@ -1168,33 +1620,69 @@ async fn graphical_ui(
                 let i = ((mouse_position.0 - PADDING_SIZE) / square_size).floor() as i32;
                 let j = ((mouse_position.1 - PADDING_SIZE) / square_size).floor() as i32;
                 if (0..BOARD_SIZE).contains(&i) && (0..BOARD_SIZE).contains(&j) {
-                    let to = CoordinateSet::new(i, j);
-                    if let Ok(new_board) = current_position.move_piece(from, to) {
-                        current_position = new_board;
+                    let to = CoordinateSet::new(i, j).oriented(flipped);
+                    if current_position.is_promotion(from, to) {
+                        pending_promotion = Some((from, to));
+                    } else {
+                        human_move = current_position.move_piece(from, to, None).ok();
+                    }
+                }
+            }
+        }
 
-                        main_out
-                            .send(MessageToBot::Move(current_position.board))
-                            .unwrap();
-                        let incoming = main_in.recv();
-                        match incoming {
-                            Ok(message) => match message {
-                                MessageToMain::Move(new_position) => {
-                                    current_position = new_position;
-                                }
-                                MessageToMain::Error(e) => {
-                                    println!("Bot received ERROR:\n{}", e);
-                                    break;
-                                }
-                            },
-                            Err(e) => {
-                                println!("Bot failed to receive:\n{}", e);
-                                break;
-                            }
+        if let Some(chosen_move) = human_move {
+            history.push(chosen_move);
+            main_out.send(MessageToBot::Move(chosen_move)).unwrap();
+        }
+        // Poll rather than block so the board keeps drawing while the bot thinks
+        if game_result.is_none() && current_position.side_to_move != player_color {
+            match main_in.try_recv() {
+                Ok(message) => match message {
+                    MessageToMain::Thinking(line) => thinking = Some(line),
+                    MessageToMain::Move(bot_move) => {
+                        current_position.make_move(&bot_move);
+                        history.push(bot_move);
+                    }
+                    MessageToMain::GameOver(result, bot_move) => {
+                        println!("{}", result);
+                        if let Some(bot_move) = bot_move {
+                            current_position.make_move(&bot_move);
+                            history.push(bot_move);
                         }
+                        save_game(&save_path, &history, player_color, result);
+                        game_result = Some(result);
                     }
+                    MessageToMain::Error(e) => {
+                        println!("Bot received ERROR:\n{}", e);
+                        break;
+                    }
+                },
+                Err(TryRecvError::Empty) => {}
+                Err(e) => {
+                    println!("Bot failed to receive:\n{}", e);
+                    break;
                 }
             }
         }
+        if is_key_pressed(KeyCode::F) {
+            flipped = !flipped;
+        }
+        // Only while the bot isn't thinking, otherwise its reply would land on the restored position
+        let bot_idle = game_result.is_some() || current_position.side_to_move == player_color;
+        if is_key_pressed(KeyCode::U) && bot_idle {
+            if let Some(restored) = history.takeback(player_color) {
+                current_position = restored.clone();
+                game_result = None;
+                dragging_piece = None;
+                pending_promotion = None;
+                thinking = None;
+                main_out
+                    .send(MessageToBot::SetPosition(Box::new(
+                        current_position.clone(),
+                    )))
+                    .unwrap();
+            }
+        }
 
         next_frame().await;
     }
@@ -1202,35 +1690,292 @@ async fn graphical_ui(
     // main_out.send(MessageToBot::Stop).unwrap();
 }
 
-#[macroquad::main("BasicShapes")]
-async fn main() {
-    let init_position = match BOTTOM_SIDE {
-        PieceColor::White => BoardPosition::from(INITIAL_BOARD),
-        PieceColor::Black => {
-            let mut half_reverse = INITIAL_BOARD.map(|mut row| {
-                row.reverse();
-                row
-            });
-            half_reverse.reverse();
-            BoardPosition::from(half_reverse)
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.iter().any(|arg| arg == "--uci") {
+        return uci::run();
+    }
+    let mut init_position = match args.iter().position(|arg| arg == "--fen") {
+        Some(index) => match args.get(index + 1).map(|fen| BoardPosition::from_fen(fen)) {
+            Some(Ok(loaded)) => loaded,
+            Some(Err(e)) => {
+                println!("Could not load FEN: {}", e);
+                return;
+            }
+            None => {
+                println!("--fen needs a FEN string after it");
+                return;
+            }
+        },
+        None => BoardPosition::from(INITIAL_BOARD),
+    };
+    if let Some(index) = args.iter().position(|arg| arg == "--perft") {
+        match args
+            .get(index + 1)
+            .and_then(|depth| depth.parse().ok())
+            .filter(|&depth| depth > 0)
+        {
+            Some(depth) => print_perft_divide(&mut init_position, depth),
+            None => println!("--perft needs a depth of at least 1 after it"),
         }
+        return;
+    }
+    let player_color = match args.iter().position(|arg| arg == "--color") {
+        Some(index) => match args.get(index + 1).map(String::as_str) {
+            Some("white") => PieceColor::White,
+            Some("black") => PieceColor::Black,
+            _ => {
+                println!("--color needs white or black after it");
+                return;
+            }
+        },
+        None => PieceColor::White,
     };
 
-    println!("Base Eval: {}", init_position.eval(PieceColor::White));
+    let time_control = match parse_time_control(&args) {
+        Ok(time_control) => time_control,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+    let history = match args.iter().position(|arg| arg == "--pgn") {
+        Some(index) => match args.get(index + 1).map(|path| {
+            std::fs::read_to_string(path)
+                .map_err(|e| e.to_string())
+                .and_then(|text| pgn::read_pgn(&text))
+        }) {
+            Some(Ok(loaded)) => loaded,
+            Some(Err(e)) => {
+                println!("Could not load PGN: {}", e);
+                return;
+            }
+            None => {
+                println!("--pgn needs a file after it");
+                return;
+            }
+        },
+        None => GameHistory::new(init_position),
+    };
+    let save_path = match args.iter().position(|arg| arg == "--save-pgn") {
+        Some(index) => match args.get(index + 1) {
+            Some(path) => path.clone(),
+            None => {
+                println!("--save-pgn needs a file after it");
+                return;
+            }
+        },
+        None => String::from("game.pgn"),
+    };
+    let mut options = SearchOptions::default();
+    if let Some(index) = args.iter().position(|arg| arg == "--threads") {
+        match args.get(index + 1).and_then(|threads| threads.parse().ok()) {
+            Some(threads) if (1..=MAX_THREADS).contains(&threads) => options.threads = threads,
+            _ => {
+                println!(
+                    "--threads needs a number from 1 to {} after it",
+                    MAX_THREADS
+                );
+                return;
+            }
+        }
+    }
+
+    if args.iter().any(|arg| arg == "--terminal") {
+        return play_terminal_game(history, player_color, time_control, options, save_path);
+    }
+    macroquad::Window::new(
+        "BasicShapes",
+        play_game(history, player_color, time_control, options, save_path),
+    );
+}
+
+/// Writes the finished game as PGN, the player is named Human and the bot Rook Bot ///
+fn save_game(path: &str, history: &GameHistory, player_color: PieceColor, result: GameResult) {
+    let (white, black) = match player_color {
+        PieceColor::White => ("Human", "Rook Bot"),
+        PieceColor::Black => ("Rook Bot", "Human"),
+    };
+    match std::fs::write(path, pgn::write_pgn(history, white, black, Some(result))) {
+        Ok(()) => println!("Saved the game to {}", path),
+        Err(e) => println!("Could not save the game to {}: {}", path, e),
+    }
+}
+
+/// Reads --movetime <seconds> or --clock <seconds> [--increment <seconds>] for the bot ///
+fn parse_time_control(args: &[String]) -> Result<TimeControl, String> {
+    let seconds = |flag: &str| -> Result<Option<Duration>, String> {
+        match args.iter().position(|arg| arg == flag) {
+            Some(index) => args
+                .get(index + 1)
+                .and_then(|value| value.parse().ok())
+                .and_then(|value| Duration::try_from_secs_f64(value).ok())
+                .map(Some)
+                .ok_or_else(|| format!("{} needs a number of seconds after it", flag)),
+            None => Ok(None),
+        }
+    };
+    match (seconds("--movetime")?, seconds("--clock")?) {
+        (Some(_), Some(_)) => Err(String::from("Use either --movetime or --clock, not both")),
+        (Some(movetime), None) => Ok(TimeControl::fixed(movetime)),
+        (None, Some(remaining)) => Ok(TimeControl::clock(
+            remaining,
+            seconds("--increment")?.unwrap_or(Duration::ZERO),
+        )),
+        (None, None) => Ok(TimeControl::default()),
+    }
+}
+
+fn print_perft_divide(position: &mut BoardPosition, depth: u32) {
+    let start = Instant::now();
+    let divide = position.perft_divide(depth);
+    for (move_name, nodes) in &divide {
+        println!("{}: {}", move_name, nodes);
+    }
+    let nodes: u64 = divide.iter().map(|(_, nodes)| nodes).sum();
+    let secs_taken = start.elapsed().as_secs_f64();
+    println!(
+        "\nNodes searched: {} in {} seconds ({} nodes/second)",
+        nodes,
+        secs_taken,
+        nodes as f64 / secs_taken
+    );
+}
+
+/// Prints the game so far and starts the bot thread playing the other color ///
+fn start_bot(
+    history: &GameHistory,
+    player_color: PieceColor,
+    time_control: TimeControl,
+    options: SearchOptions,
+) -> (
+    Sender<MessageToBot>,
+    Receiver<MessageToMain>,
+    thread::JoinHandle<()>,
+) {
+    for line in history.numbered_moves() {
+        println!("{}", line);
+    }
+    let init_position = history.current();
+    println!("FEN: {}", init_position.to_fen());
+    println!(
+        "Base Eval: {:+.2}",
+        init_position.eval(PieceColor::White, &EvalTerms::default()) as f64 / 100.0
+    );
     let (main_out, bot_in) = mpsc::channel();
     let (bot_out, main_in) = mpsc::channel();
 
+    let bot_position = init_position.clone();
     let bot = thread::spawn(move || {
         run_bot(
             bot_out,
             bot_in,
-            init_position.board,
-            PieceColor::Black,
-            PieceColor::White,
+            bot_position,
+            !player_color,
+            time_control,
+            options,
         )
     });
+    (main_out, main_in, bot)
+}
 
-    graphical_ui(main_in, main_out, PieceColor::White, init_position).await;
+async fn play_game(
+    history: GameHistory,
+    player_color: PieceColor,
+    time_control: TimeControl,
+    options: SearchOptions,
+    save_path: String,
+) {
+    let (main_out, main_in, bot) = start_bot(&history, player_color, time_control, options);
+    graphical_ui(main_in, main_out, player_color, history, save_path).await;
     println!("stopping bot");
     bot.join().unwrap();
 }
+
+fn play_terminal_game(
+    history: GameHistory,
+    player_color: PieceColor,
+    time_control: TimeControl,
+    options: SearchOptions,
+    save_path: String,
+) {
+    let (main_out, main_in, bot) = start_bot(&history, player_color, time_control, options);
+    command_line_ui(main_in, main_out, player_color, history, save_path);
+    bot.join().unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checkmate_and_stalemate() {
+        // Fool's mate
+        let mated = BoardPosition::from_fen(
+            "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3",
+        )
+        .unwrap();
+        assert_eq!(
+            mated.game_result(),
+            Some(GameResult::BlackWins(WinReason::Checkmate))
+        );
+        // The king isn't attacked but has nowhere to go
+        let stalemated = BoardPosition::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(
+            stalemated.game_result(),
+            Some(GameResult::Draw(DrawReason::Stalemate))
+        );
+        let playing = BoardPosition::from_fen("7k/8/5Q2/6K1/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(playing.game_result(), None);
+    }
+
+    #[test]
+    fn time_control_flags() {
+        let parse = |text: &str| {
+            let args: Vec<String> = text.split_whitespace().map(String::from).collect();
+            parse_time_control(&args)
+        };
+        assert_eq!(
+            parse("--movetime 1.5"),
+            Ok(TimeControl::fixed(Duration::from_millis(1500)))
+        );
+        assert_eq!(
+            parse("--clock 60 --increment 2"),
+            Ok(TimeControl::clock(
+                Duration::from_secs(60),
+                Duration::from_secs(2)
+            ))
+        );
+        for bad in [
+            "--movetime -1",
+            "--clock inf",
+            "--clock 60 --increment NaN",
+            "--movetime",
+        ] {
+            assert!(parse(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn promotion_needs_a_piece() {
+        let fen = "4k3/P7/8/8/8/8/8/4K3 w - - 0 1";
+        let from = CoordinateSet::from_algebraic("a7").unwrap();
+        let to = CoordinateSet::from_algebraic("a8").unwrap();
+        let mut position = BoardPosition::from_fen(fen).unwrap();
+        assert_eq!(
+            position.move_piece(from, to, None),
+            Err(String::from("Choose a piece to promote to"))
+        );
+        // Nothing was moved
+        assert_eq!(position.get_piece(&from).piece_type, Pawn);
+
+        for piece_type in [Queen, Knight] {
+            let mut position = BoardPosition::from_fen(fen).unwrap();
+            position.move_piece(from, to, Some(piece_type)).unwrap();
+            assert_eq!(
+                *position.get_piece(&to),
+                Piece::new(PieceColor::White, piece_type)
+            );
+        }
+    }
+}
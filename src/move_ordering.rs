@@ -0,0 +1,140 @@
+use std::cmp::Reverse;
+
+use crate::bitboard::{color_index, square_index};
+use crate::search::MAX_DEPTH;
+use crate::{BoardPosition, Move, MoveType::*, Piece, PieceType::*};
+
+/// Sort keys for each kind of move, every capture goes before every killer and so on ///
+const HASH_MOVE: i32 = i32::MAX;
+const CAPTURE: i32 = 2_000_000;
+const KILLER: i32 = 1_000_000;
+/// History scores are halved once one reaches this, so they never catch up with the killers ///
+const MAX_HISTORY: i32 = KILLER / 2;
+
+/// Most valuable victim, least valuable attacker: taking a queen with a pawn
+/// is tried before taking a pawn with a queen ///
+pub(crate) fn mvv_lva(position: &BoardPosition, chess_move: &Move) -> i32 {
+    let victim = match chess_move.kind {
+        EnPassante => Piece::new(!position.side_to_move, Pawn),
+        _ => *position.get_piece(&chess_move.to),
+    };
+    let attacker = position.get_piece(&chess_move.from);
+    // Promoting is worth about as much as taking the piece promoted to
+    let promotion = chess_move.promotion.map_or(0, |piece_type| {
+        Piece::new(attacker.color, piece_type).point_value()
+    });
+    (victim.point_value() + promotion) * 100 - attacker.point_value().min(99)
+}
+
+/// What a search learns about which quiet moves tend to cause cutoffs ///
+pub(crate) struct MoveOrdering {
+    /// Per ply, the last two quiet moves that caused a cutoff there ///
+    killers: Vec<[Option<Move>; 2]>,
+    /// Indexed by the color moving and the from and to squares, grows by depth squared
+    /// every time the move causes a cutoff ///
+    history: Box<[[[i32; 64]; 64]; 2]>,
+}
+
+impl MoveOrdering {
+    pub(crate) fn new() -> MoveOrdering {
+        MoveOrdering {
+            killers: vec![[None; 2]; MAX_DEPTH as usize + 1],
+            history: Box::new([[[0; 64]; 64]; 2]),
+        }
+    }
+
+    /// Puts the moves most likely to cause a cutoff first: the hash move,
+    /// then captures and promotions by MVV-LVA, then killers, then the rest by history ///
+    pub(crate) fn order(
+        &self,
+        position: &BoardPosition,
+        moves: &mut [Move],
+        hash_move: Option<Move>,
+        ply: u32,
+    ) {
+        let killers = self.killers.get(ply as usize).copied().unwrap_or_default();
+        moves.sort_by_cached_key(|chess_move| {
+            Reverse(if Some(*chess_move) == hash_move {
+                HASH_MOVE
+            } else if chess_move.is_tactical() {
+                CAPTURE + mvv_lva(position, chess_move)
+            } else if killers[0] == Some(*chess_move) {
+                KILLER + 1
+            } else if killers[1] == Some(*chess_move) {
+                KILLER
+            } else {
+                self.history_score(position, chess_move)
+            })
+        });
+    }
+
+    fn history_score(&self, position: &BoardPosition, chess_move: &Move) -> i32 {
+        self.history[color_index(position.side_to_move)][square_index(&chess_move.from)]
+            [square_index(&chess_move.to)]
+    }
+
+    /// Remembers a quiet move that caused a beta cutoff at ply, searched to depth ///
+    pub(crate) fn add_cutoff(
+        &mut self,
+        position: &BoardPosition,
+        chess_move: &Move,
+        depth: u32,
+        ply: u32,
+    ) {
+        if let Some(killers) = self.killers.get_mut(ply as usize) {
+            if killers[0] != Some(*chess_move) {
+                killers[1] = killers[0];
+                killers[0] = Some(*chess_move);
+            }
+        }
+        let history = &mut self.history[color_index(position.side_to_move)];
+        let score = &mut history[square_index(&chess_move.from)][square_index(&chess_move.to)];
+        *score += (depth * depth) as i32;
+        if *score >= MAX_HISTORY {
+            for score in history.iter_mut().flatten() {
+                *score /= 2;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ordered(
+        position: &mut BoardPosition,
+        ordering: &MoveOrdering,
+        hash_move: Option<&str>,
+    ) -> Vec<String> {
+        let mut moves = position.eval_moves();
+        let hash_move = hash_move.map(|text| position.parse_long_algebraic(text).unwrap());
+        ordering.order(position, &mut moves, hash_move, 3);
+        moves.iter().map(Move::to_string).collect()
+    }
+
+    #[test]
+    fn move_order() {
+        // The pawn can take the rook and the queen can take a pawn
+        let mut position = BoardPosition::from_fen("4k3/8/2r5/1P6/8/8/p7/Q3K3 w - - 0 1").unwrap();
+        let mut ordering = MoveOrdering::new();
+        assert_eq!(
+            ordered(&mut position, &ordering, None)[..2],
+            ["b5c6", "a1a2"]
+        );
+        assert_eq!(
+            ordered(&mut position, &ordering, Some("e1d2"))[..3],
+            ["e1d2", "b5c6", "a1a2"]
+        );
+
+        // Killers only count at the ply they were found, history counts everywhere
+        let killer = position.parse_long_algebraic("e1f2").unwrap();
+        let history = position.parse_long_algebraic("a1b1").unwrap();
+        ordering.add_cutoff(&position, &history, 2, 5);
+        ordering.add_cutoff(&position, &killer, 1, 3);
+        assert_eq!(
+            ordered(&mut position, &ordering, None)[..4],
+            ["b5c6", "a1a2", "e1f2", "a1b1"]
+        );
+    }
+}
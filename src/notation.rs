@@ -0,0 +1,282 @@
+use crate::{BoardPosition, CoordinateSet, Move, MoveType::*, PieceType, PieceType::*};
+
+/// Upper case SAN letter for a piece, pawns have none ///
+pub(crate) fn piece_letter(piece_type: PieceType) -> Option<char> {
+    match piece_type {
+        Knight => Some('N'),
+        Bishop => Some('B'),
+        Rook { .. } => Some('R'),
+        Queen => Some('Q'),
+        King { .. } => Some('K'),
+        Pawn | Empty => None,
+    }
+}
+
+impl BoardPosition {
+    /// Standard Algebraic Notation for a legal move in this position, e.g. Nbd2, exd5, O-O or e8=Q# ///
+    pub(crate) fn san(&mut self, chess_move: &Move) -> String {
+        let moves = self.eval_moves();
+        debug_assert!(moves.contains(chess_move), "{} is not legal", chess_move);
+        let piece_type = self.get_piece(&chess_move.from).piece_type;
+        let mut san = String::new();
+        if chess_move.kind == Castle {
+            san.push_str(match chess_move.to.x > chess_move.from.x {
+                true => "O-O",
+                false => "O-O-O",
+            });
+        } else {
+            match piece_letter(piece_type) {
+                Some(letter) => {
+                    san.push(letter);
+                    san.push_str(&self.disambiguation(chess_move, &moves));
+                }
+                // Pawn captures always name the file they came from
+                None if chess_move.is_capture() => {
+                    san.push(chess_move.from.to_algebraic().chars().next().unwrap())
+                }
+                None => {}
+            }
+            if chess_move.is_capture() {
+                san.push('x');
+            }
+            san.push_str(&chess_move.to.to_algebraic());
+            if let Some(letter) = chess_move.promotion.and_then(piece_letter) {
+                san.push('=');
+                san.push(letter);
+            }
+        }
+
+        let undo = self.make_move(chess_move);
+        if self.is_in_check(self.side_to_move) {
+            san.push(match self.eval_moves().is_empty() {
+                true => '#',
+                false => '+',
+            });
+        }
+        self.unmake_move(chess_move, undo);
+        san
+    }
+
+    /// The file, rank or whole square needed to tell chess_move apart from
+    /// the same kind of piece moving to the same square ///
+    fn disambiguation(&self, chess_move: &Move, moves: &[Move]) -> String {
+        let piece = self.get_piece(&chess_move.from);
+        let rivals: Vec<&Move> = moves
+            .iter()
+            .filter(|other| {
+                other.to == chess_move.to
+                    && other.from != chess_move.from
+                    && self.get_piece(&other.from) == piece
+            })
+            .collect();
+        let from = chess_move.from.to_algebraic();
+        if rivals.is_empty() {
+            String::new()
+        } else if rivals.iter().all(|other| other.from.x != chess_move.from.x) {
+            from[..1].to_string()
+        } else if rivals.iter().all(|other| other.from.y != chess_move.from.y) {
+            from[1..].to_string()
+        } else {
+            from
+        }
+    }
+
+    /// Finds the legal move written as SAN, e.g. Nf3, exd5, O-O-O or e8=Q+.
+    /// Check marks, annotations like !? and the = before a promotion are optional,
+    /// and castling may be written with zeros.
+    pub(crate) fn parse_san(&mut self, text: &str) -> Result<Move, String> {
+        let san = text.trim().trim_end_matches(['+', '#', '!', '?']);
+        let moves = self.eval_moves();
+        let castle_side = match san {
+            "O-O" | "0-0" => Some(true),
+            "O-O-O" | "0-0-0" => Some(false),
+            _ => None,
+        };
+        if let Some(king_side) = castle_side {
+            return moves
+                .into_iter()
+                .find(|chess_move| {
+                    chess_move.kind == Castle && (chess_move.to.x > chess_move.from.x) == king_side
+                })
+                .ok_or_else(|| format!("Can't castle with '{}' here", text));
+        }
+
+        let mut chars: Vec<char> = san.chars().collect();
+        let piece = match chars.first().copied().and_then(piece_from_letter) {
+            Some(piece_type) => {
+                chars.remove(0);
+                Some(piece_type)
+            }
+            None => None,
+        };
+        let promotion = match chars.last().copied().and_then(piece_from_letter) {
+            Some(piece_type) if piece.is_none() => {
+                chars.pop();
+                if chars.last() == Some(&'=') {
+                    chars.pop();
+                }
+                Some(piece_type)
+            }
+            _ => None,
+        };
+        if chars.len() < 2 {
+            return Err(format!("'{}' has no destination square", text));
+        }
+        let destination: String = chars.split_off(chars.len() - 2).into_iter().collect();
+        let to = CoordinateSet::from_algebraic(&destination)
+            .ok_or_else(|| format!("'{}' has no destination square", text))?;
+        // What is left names where the piece came from, and maybe an x for the capture
+        if chars.last() == Some(&'x') {
+            chars.pop();
+        }
+        let (mut from_file, mut from_rank) = (None, None);
+        for hint in chars {
+            match hint {
+                'a'..='h' if from_file.is_none() && from_rank.is_none() => {
+                    from_file = Some(hint as i32 - 'a' as i32)
+                }
+                '1'..='8' if from_rank.is_none() => from_rank = Some(hint as i32 - '1' as i32),
+                _ => return Err(format!("Can't read '{}' as a move", text)),
+            }
+        }
+
+        let candidates: Vec<Move> = moves
+            .into_iter()
+            .filter(|chess_move| {
+                let (file, rank) = chess_move.from.file_rank();
+                let moved = self.get_piece(&chess_move.from).piece_type;
+                chess_move.to == to
+                    && chess_move.kind != Castle
+                    && piece_letter(moved) == piece.and_then(piece_letter)
+                    && from_file.is_none_or(|from_file| from_file == file)
+                    && from_rank.is_none_or(|from_rank| from_rank == rank)
+                    && chess_move.promotion.and_then(piece_letter)
+                        == promotion.and_then(piece_letter)
+            })
+            .collect();
+        match candidates[..] {
+            [chess_move] => Ok(chess_move),
+            [] => Err(format!("'{}' is not a legal move", text)),
+            _ => Err(format!("'{}' could be more than one move", text)),
+        }
+    }
+
+    /// Finds the legal move written in long algebraic notation as UCI uses it, e.g. e2e4 or e7e8q.
+    /// A dash between the squares and an upper case promotion letter are accepted too.
+    pub(crate) fn parse_long_algebraic(&mut self, text: &str) -> Result<Move, String> {
+        let normalised = text.trim().replace('-', "").to_ascii_lowercase();
+        self.eval_moves()
+            .into_iter()
+            .find(|chess_move| chess_move.to_string() == normalised)
+            .ok_or_else(|| format!("'{}' is not a legal move", text))
+    }
+
+    /// Reads a move typed in either SAN or long algebraic notation ///
+    pub(crate) fn parse_move(&mut self, text: &str) -> Result<Move, String> {
+        self.parse_long_algebraic(text)
+            .or_else(|_| self.parse_san(text))
+    }
+}
+
+/// The piece a SAN letter stands for, pawns have none ///
+fn piece_from_letter(letter: char) -> Option<PieceType> {
+    PieceType::to_promote()
+        .into_iter()
+        .chain([King { has_moved: true }])
+        .find(|&piece_type| piece_letter(piece_type) == Some(letter))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BoardPosition;
+
+    fn all_san(fen: &str) -> Vec<String> {
+        let mut position = BoardPosition::from_fen(fen).unwrap();
+        let mut sans: Vec<String> = position
+            .eval_moves()
+            .iter()
+            .map(|chess_move| position.san(chess_move))
+            .collect();
+        sans.sort();
+        sans
+    }
+
+    #[test]
+    fn disambiguation() {
+        // Knights on b1 and f1 can both reach d2, rooks on a1 and a5 can both reach a3
+        let sans = all_san("4k3/8/8/R7/8/8/8/RN2KN2 w - - 0 1");
+        assert!(sans.contains(&String::from("Nbd2")));
+        assert!(sans.contains(&String::from("Nfd2")));
+        assert!(sans.contains(&String::from("R1a3")));
+        assert!(sans.contains(&String::from("R5a3")));
+        assert!(sans.contains(&String::from("Nc3")));
+
+        // Three queens reaching e1 need the full square for one of them
+        let sans = all_san("1k6/8/8/8/4Q2Q/8/8/K6Q w - - 0 1");
+        assert!(sans.contains(&String::from("Qh4e1")));
+        assert!(sans.contains(&String::from("Qee1")));
+    }
+
+    #[test]
+    fn special_moves() {
+        let sans = all_san("r3k2r/1P6/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1");
+        for expected in [
+            "O-O", "O-O-O", "exd6", "b8=Q+", "bxa8=Q+", "bxa8=N", "Rxa8+",
+        ] {
+            assert!(sans.contains(&String::from(expected)), "{}", expected);
+        }
+        let sans = all_san("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        assert!(sans.contains(&String::from("Ra8#")));
+    }
+
+    #[test]
+    fn parse_round_trip() {
+        let mut position =
+            BoardPosition::from_fen("r3k2r/1P6/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1").unwrap();
+        for chess_move in position.eval_moves() {
+            let san = position.san(&chess_move);
+            assert_eq!(position.parse_san(&san), Ok(chess_move));
+        }
+        assert!(position.parse_san("0-0-0").is_ok());
+        assert!(position.parse_san("bxa8=Q").is_ok());
+        assert!(position.parse_san("Nf3").is_err());
+    }
+
+    #[test]
+    fn lenient_san() {
+        let mut position =
+            BoardPosition::from_fen("r3k2r/1P6/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1").unwrap();
+        let promotion = position.parse_san("bxa8=Q+").unwrap();
+        assert_eq!(position.parse_san("bxa8Q"), Ok(promotion));
+        assert_eq!(position.parse_san("ba8=Q!?"), Ok(promotion));
+        assert_eq!(position.parse_san("exd6").unwrap().to_string(), "e5d6");
+        // Both the rook and the pawn take on a8, and the pawn needs a piece to promote to
+        assert!(position.parse_san("xa8").is_err());
+        assert!(position.parse_san("bxa8").is_err());
+        assert!(position.parse_san("Kf2+").is_ok());
+        assert!(position.parse_san("Ke9").is_err());
+
+        // Both knights reach d2 so the file is needed
+        let mut position = BoardPosition::from_fen("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1").unwrap();
+        assert!(position.parse_san("Nd2").is_err());
+        assert_eq!(position.parse_san("Nbd2").unwrap().to_string(), "b1d2");
+        assert!(position.parse_san("N1d2").is_err());
+        assert_eq!(position.parse_san("Nb1d2").unwrap().to_string(), "b1d2");
+    }
+
+    #[test]
+    fn long_algebraic() {
+        let mut position =
+            BoardPosition::from_fen("r3k2r/1P6/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1").unwrap();
+        for chess_move in position.eval_moves() {
+            assert_eq!(
+                position.parse_long_algebraic(&chess_move.to_string()),
+                Ok(chess_move)
+            );
+        }
+        assert_eq!(position.parse_move("e1g1"), position.parse_san("O-O"));
+        assert_eq!(position.parse_move("b7-a8N"), position.parse_san("bxa8=N"));
+        assert_eq!(position.parse_move("Rxa8"), position.parse_move("a1a8"));
+        assert!(position.parse_move("b7a8").is_err());
+    }
+}
@@ -0,0 +1,131 @@
+use crate::BoardPosition;
+
+impl BoardPosition {
+    /// Number of positions reachable in exactly depth legal moves ///
+    pub(crate) fn perft(&mut self, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let moves = self.eval_moves();
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+        moves
+            .iter()
+            .map(|chess_move| {
+                let undo = self.make_move(chess_move);
+                let nodes = self.perft(depth - 1);
+                self.unmake_move(chess_move, undo);
+                nodes
+            })
+            .sum()
+    }
+
+    /// Perft split by root move, so a wrong total can be narrowed down to one move.
+    /// Depth 0 makes no moves, so there is nothing to split ///
+    pub(crate) fn perft_divide(&mut self, depth: u32) -> Vec<(String, u64)> {
+        if depth == 0 {
+            return Vec::new();
+        }
+        self.eval_moves()
+            .iter()
+            .map(|chess_move| {
+                let undo = self.make_move(chess_move);
+                let nodes = self.perft(depth - 1);
+                self.unmake_move(chess_move, undo);
+                (chess_move.to_string(), nodes)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BoardPosition;
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+    const POSITION_3: &str = "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1";
+    const POSITION_4: &str = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1";
+    const POSITION_5: &str = "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8";
+    const POSITION_6: &str =
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10";
+
+    fn assert_perft(fen: &str, expected: &[u64]) {
+        let mut position = BoardPosition::from_fen(fen).unwrap();
+        for (depth, &nodes) in expected.iter().enumerate() {
+            let depth = depth as u32 + 1;
+            assert_eq!(
+                position.perft(depth),
+                nodes,
+                "perft({}) of {}\n{:#?}",
+                depth,
+                fen,
+                position.perft_divide(depth)
+            );
+        }
+    }
+
+    #[test]
+    fn start_position() {
+        assert_perft(START, &[20, 400, 8902]);
+    }
+
+    #[test]
+    fn kiwipete() {
+        assert_perft(KIWIPETE, &[48, 2039, 97862]);
+    }
+
+    #[test]
+    fn position_3() {
+        assert_perft(POSITION_3, &[14, 191, 2812]);
+    }
+
+    #[test]
+    fn position_4() {
+        assert_perft(POSITION_4, &[6, 264, 9467]);
+    }
+
+    #[test]
+    fn position_5() {
+        assert_perft(POSITION_5, &[44, 1486, 62379]);
+    }
+
+    #[test]
+    fn position_6() {
+        assert_perft(POSITION_6, &[46, 2079, 89890]);
+    }
+
+    #[test]
+    #[ignore = "slow in debug builds, run with --release -- --ignored"]
+    fn deep() {
+        assert_perft(START, &[20, 400, 8902, 197281, 4865609]);
+        assert_perft(KIWIPETE, &[48, 2039, 97862, 4085603]);
+        assert_perft(POSITION_3, &[14, 191, 2812, 43238, 674624]);
+        assert_perft(POSITION_4, &[6, 264, 9467, 422333]);
+        assert_perft(POSITION_5, &[44, 1486, 62379, 2103487]);
+        assert_perft(POSITION_6, &[46, 2079, 89890, 3894594]);
+    }
+
+    #[test]
+    fn divide_sums_to_perft() {
+        let mut position = BoardPosition::from_fen(KIWIPETE).unwrap();
+        let divide = position.perft_divide(2);
+        assert_eq!(divide.len(), 48);
+        assert_eq!(divide.iter().map(|(_, nodes)| nodes).sum::<u64>(), 2039);
+        assert!(divide.iter().any(|(name, _)| name == "e1g1"));
+        assert!(position.perft_divide(0).is_empty());
+    }
+
+    #[test]
+    fn make_unmake_restores_position() {
+        for fen in [
+            START, KIWIPETE, POSITION_3, POSITION_4, POSITION_5, POSITION_6,
+        ] {
+            let mut position = BoardPosition::from_fen(fen).unwrap();
+            position.perft(3);
+            assert_eq!(position.to_fen(), fen);
+            assert_eq!(position.board, BoardPosition::from_fen(fen).unwrap().board);
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::fen::STARTING_FEN;
+use crate::history::GameHistory;
+use crate::{BoardPosition, GameResult};
+
+/// Movetext lines are wrapped before this many characters ///
+const LINE_LENGTH: usize = 80;
+const RESULT_TOKENS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+fn result_token(result: Option<GameResult>) -> &'static str {
+    match result {
+        Some(GameResult::WhiteWins(_)) => "1-0",
+        Some(GameResult::BlackWins(_)) => "0-1",
+        Some(GameResult::Draw(_)) => "1/2-1/2",
+        None => "*",
+    }
+}
+
+/// Today's date as PGN writes it, e.g. 2024.03.09 ///
+fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs() / 86400) as i64;
+    // Converts days since 1970-01-01 to a civil date, see Howard Hinnant's days_from_civil
+    let shifted = days + 719468;
+    let era = shifted.div_euclid(146097);
+    let day_of_era = shifted.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+/// The game as PGN with the seven tag roster, plus SetUp and FEN if it didn't start from the usual position ///
+pub(crate) fn write_pgn(
+    history: &GameHistory,
+    white: &str,
+    black: &str,
+    result: Option<GameResult>,
+) -> String {
+    let result = result_token(result);
+    let mut pgn = format!(
+        "[Event \"Rook Bot game\"]\n[Site \"?\"]\n[Date \"{}\"]\n[Round \"-\"]\n[White \"{}\"]\n[Black \"{}\"]\n[Result \"{}\"]\n",
+        today(),
+        white,
+        black,
+        result
+    );
+    let start_fen = history.start().to_fen();
+    if start_fen != STARTING_FEN {
+        pgn.push_str(&format!("[SetUp \"1\"]\n[FEN \"{}\"]\n", start_fen));
+    }
+    pgn.push('\n');
+
+    let mut line = String::new();
+    let words = history
+        .numbered_moves()
+        .into_iter()
+        .flat_map(|pair| pair.split(' ').map(String::from).collect::<Vec<String>>())
+        .chain([String::from(result)]);
+    for word in words {
+        if !line.is_empty() && line.len() + 1 + word.len() > LINE_LENGTH {
+            pgn.push_str(&line);
+            pgn.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&word);
+    }
+    pgn.push_str(&line);
+    pgn.push('\n');
+    pgn
+}
+
+/// Reads the first game of a PGN and replays its moves, only the FEN tag is used ///
+pub(crate) fn read_pgn(text: &str) -> Result<GameHistory, String> {
+    let mut start = BoardPosition::from_fen(STARTING_FEN).unwrap();
+    let mut movetext = String::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if let Some(tag) = line.strip_prefix('[') {
+            let (name, value) = tag
+                .trim_end_matches(']')
+                .split_once(' ')
+                .ok_or_else(|| format!("Invalid tag '{}'", line))?;
+            if name == "FEN" {
+                start = BoardPosition::from_fen(value.trim().trim_matches('"'))?;
+            }
+        } else if line.is_empty() && !movetext.trim().is_empty() {
+            // A blank line after the moves ends the game
+            break;
+        } else {
+            movetext.push_str(line);
+            movetext.push('\n');
+        }
+    }
+
+    let mut history = GameHistory::new(start);
+    for token in movetext_tokens(&movetext)? {
+        // Move numbers may be written straight onto the move, as in 1.e4
+        let after_digits = token.trim_start_matches(|c: char| c.is_ascii_digit());
+        let san = match after_digits.starts_with('.') {
+            true => after_digits.trim_start_matches('.'),
+            false => token.as_str(),
+        };
+        if san.is_empty() || san.starts_with('$') || RESULT_TOKENS.contains(&token.as_str()) {
+            continue;
+        }
+        let chess_move = history
+            .current()
+            .clone()
+            .parse_san(san)
+            .map_err(|e| format!("Move {}: {}", history.sans().len() + 1, e))?;
+        history.push(chess_move);
+    }
+    Ok(history)
+}
+
+/// Splits movetext into tokens, dropping comments and variations ///
+fn movetext_tokens(movetext: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut variation_depth = 0;
+    let mut chars = movetext.chars();
+    while let Some(letter) = chars.next() {
+        match letter {
+            '{' => {
+                chars
+                    .find(|&c| c == '}')
+                    .ok_or_else(|| String::from("Unclosed { comment"))?;
+            }
+            ';' => {
+                chars.find(|&c| c == '\n');
+            }
+            '(' => variation_depth += 1,
+            ')' => variation_depth -= 1,
+            _ if variation_depth > 0 => {}
+            _ if letter.is_whitespace() => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+                continue;
+            }
+            _ => {
+                token.push(letter);
+                continue;
+            }
+        }
+        // Comments and variations also end a token
+        if !token.is_empty() {
+            tokens.push(std::mem::take(&mut token));
+        }
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WinReason;
+
+    #[test]
+    fn round_trip() {
+        let pgn = "[Event \"Casual\"]\n[White \"A\"]\n\n1. e4 {best by test} e5 2. Nf3 (2. f4 exf4) Nc6\n3.Bb5 a6 $1 4. Ba4 Nf6 5. O-O Be7 1/2-1/2\n";
+        let history = read_pgn(pgn).unwrap();
+        assert_eq!(history.sans().len(), 10);
+        assert_eq!(
+            history.current().to_fen(),
+            "r1bqk2r/1pppbppp/p1n2n2/4p3/B3P3/5N2/PPPP1PPP/RNBQ1RK1 w kq - 4 6"
+        );
+
+        let written = write_pgn(&history, "Human", "Rook Bot", None);
+        assert!(written.starts_with("[Event \"Rook Bot game\"]\n[Site \"?\"]\n[Date \""));
+        assert!(written.contains("[Result \"*\"]\n\n1. e4 e5 2. Nf3 Nc6 3. Bb5 a6"));
+        assert!(written.ends_with("5. O-O Be7 *\n"));
+        assert!(!written.contains("FEN"));
+        assert_eq!(
+            read_pgn(&written).unwrap().current().to_fen(),
+            history.current().to_fen()
+        );
+    }
+
+    #[test]
+    fn from_fen() {
+        let fen = "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1";
+        let pgn = format!("[FEN \"{}\"]\n[SetUp \"1\"]\n\n1. Ra8# 1-0\n", fen);
+        let history = read_pgn(&pgn).unwrap();
+        assert_eq!(history.sans(), ["Ra8#"]);
+        let written = write_pgn(
+            &history,
+            "Human",
+            "Rook Bot",
+            Some(GameResult::WhiteWins(WinReason::Checkmate)),
+        );
+        assert!(written.contains(&format!("[SetUp \"1\"]\n[FEN \"{}\"]", fen)));
+        assert!(written.ends_with("1. Ra8# 1-0\n"));
+
+        assert!(read_pgn("1. e4 e4").is_err());
+    }
+}
@@ -0,0 +1,144 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::search::{SearchOptions, SearchResult, Searcher};
+use crate::time_manager::TimeBudget;
+use crate::transposition::TranspositionTable;
+use crate::{BoardPosition, Move};
+
+/// A search of the position after the reply the bot expects, run on the opponent's time.
+/// A ponderhit turns it into the search for the bot's next move, keeping everything it found.
+/// Dropping it is a ponder miss: the search stops, but what it stored in the table stays.
+pub(crate) struct PonderSearch {
+    /// The reply the search assumes ///
+    pub(crate) predicted: Move,
+    /// When the search began, its node count covers the time since ///
+    pub(crate) started: Instant,
+    /// Cleared on a ponderhit, which starts the search's clock ///
+    pondering: Arc<AtomicBool>,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<Option<SearchResult>>>,
+}
+
+impl PonderSearch {
+    /// Starts searching position after predicted on its own thread. budget is for the bot's
+    /// next move, and on_iteration is only called once the prediction came true.
+    pub(crate) fn start(
+        position: &BoardPosition,
+        predicted: Move,
+        budget: Option<TimeBudget>,
+        max_depth: u32,
+        tt: Arc<TranspositionTable>,
+        options: SearchOptions,
+        mut on_iteration: impl FnMut(&SearchResult) + Send + 'static,
+    ) -> PonderSearch {
+        let started = Instant::now();
+        let pondering = Arc::new(AtomicBool::new(true));
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut position = position.clone();
+        position.make_move(&predicted);
+        let (search_pondering, search_stop) = (pondering.clone(), stop.clone());
+        let handle = thread::spawn(move || {
+            // Lines for a reply that may never come are held back until the ponderhit
+            let mut held = None;
+            let result = Searcher::new(search_stop.clone(), budget, &tt, options)
+                .ponder(search_pondering.clone())
+                .iterative_deepening(&mut position, max_depth, &mut |result| {
+                    if search_pondering.load(Ordering::Relaxed) {
+                        held = Some(result.clone());
+                    } else {
+                        held = None;
+                        on_iteration(result);
+                    }
+                });
+            // A search that ended early still waits to hear whether its reply was played
+            while search_pondering.load(Ordering::Relaxed) && !search_stop.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            if !search_stop.load(Ordering::Relaxed) {
+                if let Some(held) = held {
+                    on_iteration(&held);
+                }
+            }
+            result
+        });
+        PonderSearch {
+            predicted,
+            started,
+            pondering,
+            stop,
+            handle: Some(handle),
+        }
+    }
+
+    /// The opponent played the predicted move: the search goes on within the budget,
+    /// which starts now, and its result is the bot's move ///
+    pub(crate) fn ponderhit(mut self) -> Option<SearchResult> {
+        self.pondering.store(false, Ordering::Relaxed);
+        self.handle.take().and_then(|handle| handle.join().unwrap())
+    }
+}
+
+impl Drop for PonderSearch {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            handle.join().unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time_manager::TimeControl;
+    use crate::transposition::DEFAULT_SIZE_MB;
+    use std::sync::mpsc;
+
+    /// Black is expected to play Ra7, after which Rb8 is mate ///
+    fn start(tt: Arc<TranspositionTable>) -> (PonderSearch, mpsc::Receiver<SearchResult>) {
+        let mut position = BoardPosition::from_fen("r6k/6pp/8/8/8/8/8/1R4K1 b - - 0 1").unwrap();
+        let predicted = position.parse_long_algebraic("a8a7").unwrap();
+        let budget = TimeControl::fixed(Duration::from_millis(100)).budget();
+        let (sender, receiver) = mpsc::channel();
+        let search = PonderSearch::start(
+            &position,
+            predicted,
+            budget,
+            6,
+            tt,
+            SearchOptions::default(),
+            move |result| sender.send(result.clone()).unwrap(),
+        );
+        (search, receiver)
+    }
+
+    #[test]
+    fn ponderhit() {
+        let (search, iterations) = start(Arc::new(TranspositionTable::new(DEFAULT_SIZE_MB)));
+        // Far past the budget, but the clock doesn't run until the ponderhit
+        thread::sleep(Duration::from_millis(200));
+        assert!(iterations.try_recv().is_err());
+        let result = search.ponderhit().unwrap();
+        assert_eq!(result.pv[0].to_string(), "b1b8");
+        assert_eq!(result.mate_in(), Some(1));
+        assert_eq!(iterations.try_iter().last().unwrap().depth, result.depth);
+    }
+
+    #[test]
+    fn ponder_miss() {
+        let tt = Arc::new(TranspositionTable::new(DEFAULT_SIZE_MB));
+        let (search, iterations) = start(tt.clone());
+        thread::sleep(Duration::from_millis(50));
+        let mut expected = BoardPosition::from_fen("r6k/6pp/8/8/8/8/8/1R4K1 b - - 0 1").unwrap();
+        let predicted = search.predicted;
+        expected.make_move(&predicted);
+        drop(search);
+        // Nothing is reported for the wrong reply, but the table keeps what was searched
+        assert!(iterations.try_recv().is_err());
+        let entry = tt.probe(expected.hash).unwrap();
+        assert_eq!(entry.best_move.unwrap().to_string(), "b1b8");
+    }
+}
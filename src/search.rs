@@ -0,0 +1,772 @@
+use std::cmp::Reverse;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::eval::EvalTerms;
+use crate::move_ordering::{mvv_lva, MoveOrdering};
+use crate::time_manager::TimeBudget;
+use crate::transposition::{Bound, TranspositionTable};
+use crate::{BoardPosition, Move, PieceColor, PieceType::*};
+
+/// Score for delivering mate right now, mates further away score less ///
+pub(crate) const MATE_SCORE: i32 = 1000000;
+const INFINITY: i32 = MATE_SCORE + 1;
+/// Scores within this many plies of MATE_SCORE are forced mates ///
+pub(crate) const MAX_MATE_PLY: i32 = 1000;
+/// How many nodes are searched between checks of the stop flag ///
+const STOP_CHECK_INTERVAL: u64 = 1024;
+/// Deepest iteration a timed search may start, in practice the clock runs out first ///
+pub(crate) const MAX_DEPTH: u32 = 64;
+/// Most threads a search may be split over ///
+pub(crate) const MAX_THREADS: usize = 256;
+/// Null-move pruning is only tried with at least this much depth left ///
+const NULL_MOVE_MIN_DEPTH: u32 = 3;
+/// How many moves at a node are searched to full depth before late-move reductions start ///
+const LMR_FULL_DEPTH_MOVES: usize = 3;
+/// Late-move reductions are only tried with at least this much depth left ///
+const LMR_MIN_DEPTH: u32 = 3;
+/// Iterations from this depth on search a narrow window around the previous score first ///
+const ASPIRATION_MIN_DEPTH: u32 = 4;
+/// Half the width of the first aspiration window in centipawns, doubled every time it fails ///
+const ASPIRATION_WINDOW: i32 = 25;
+
+#[derive(Debug, Clone)]
+pub(crate) struct SearchResult {
+    /// Score from the point of view of the side to move at the root ///
+    pub(crate) score: i32,
+    pub(crate) depth: u32,
+    pub(crate) nodes: u64,
+    /// The principal variation, the first move is the best move ///
+    pub(crate) pv: Vec<Move>,
+}
+
+impl SearchResult {
+    /// Full moves until mate, negative when the side to move is the one getting mated ///
+    pub(crate) fn mate_in(&self) -> Option<i32> {
+        if self.score.abs() < MATE_SCORE - MAX_MATE_PLY {
+            return None;
+        }
+        let moves = (MATE_SCORE - self.score.abs() + 1) / 2;
+        Some(if self.score > 0 { moves } else { -moves })
+    }
+}
+
+/// Settings that change how the search plays, the UCI front-end lets them be set as options ///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct SearchOptions {
+    pub(crate) eval: EvalTerms,
+    pub(crate) null_move: bool,
+    pub(crate) late_move_reductions: bool,
+    /// Threads searching each position, the extra ones only help by filling the transposition table ///
+    pub(crate) threads: usize,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        SearchOptions {
+            eval: EvalTerms::default(),
+            null_move: true,
+            late_move_reductions: true,
+            threads: 1,
+        }
+    }
+}
+
+/// State shared by every node of a single search ///
+pub(crate) struct Searcher<'a> {
+    /// Set from another thread to end the search early ///
+    stop: Arc<AtomicBool>,
+    /// Kept between searches so later moves reuse what earlier ones found, and shared by every thread ///
+    tt: &'a TranspositionTable,
+    options: SearchOptions,
+    ordering: MoveOrdering,
+    /// Ply of the innermost null move in the line being searched, so two never follow each other ///
+    null_move_ply: Option<u32>,
+    /// Plies a helper thread searches ahead of the main one, so they don't all search the same depth ///
+    depth_offset: u32,
+    /// Nodes searched so far by this search's helper threads ///
+    helper_nodes: Arc<AtomicU64>,
+    /// In a helper, the main search's helper_nodes, which it adds its own nodes to ///
+    main_nodes: Option<Arc<AtomicU64>>,
+    budget: Option<TimeBudget>,
+    /// Set while searching on the opponent's time, the budget only applies once it is cleared ///
+    pondering: Option<Arc<AtomicBool>>,
+    start: Instant,
+    nodes: u64,
+    stopped: bool,
+}
+
+impl<'a> Searcher<'a> {
+    /// The clock for budget starts now ///
+    pub(crate) fn new(
+        stop: Arc<AtomicBool>,
+        budget: Option<TimeBudget>,
+        tt: &'a TranspositionTable,
+        options: SearchOptions,
+    ) -> Searcher<'a> {
+        Searcher {
+            stop,
+            tt,
+            options,
+            ordering: MoveOrdering::new(),
+            null_move_ply: None,
+            depth_offset: 0,
+            helper_nodes: Arc::new(AtomicU64::new(0)),
+            main_nodes: None,
+            budget,
+            pondering: None,
+            start: Instant::now(),
+            nodes: 0,
+            stopped: false,
+        }
+    }
+
+    /// Counts a node and returns whether the search has to stop ///
+    fn count_node(&mut self) -> bool {
+        self.nodes += 1;
+        if self.nodes.is_multiple_of(STOP_CHECK_INTERVAL) {
+            if let Some(main_nodes) = &self.main_nodes {
+                main_nodes.fetch_add(STOP_CHECK_INTERVAL, Ordering::Relaxed);
+            }
+            if self.should_stop() {
+                self.stopped = true;
+            }
+        }
+        self.stopped
+    }
+
+    /// Nodes searched by this search and its helpers ///
+    fn total_nodes(&self) -> u64 {
+        self.nodes + self.helper_nodes.load(Ordering::Relaxed)
+    }
+
+    fn should_stop(&mut self) -> bool {
+        self.stop.load(Ordering::Relaxed) || self.past(|budget| budget.hard)
+    }
+
+    /// Holds off the time budget until pondering is cleared ///
+    pub(crate) fn ponder(mut self, pondering: Arc<AtomicBool>) -> Searcher<'a> {
+        self.pondering = Some(pondering);
+        self
+    }
+
+    /// Whether the clock has passed a limit of the budget, it only starts once pondering ends ///
+    fn past(&mut self, limit: fn(TimeBudget) -> Duration) -> bool {
+        if let Some(pondering) = &self.pondering {
+            if pondering.load(Ordering::Relaxed) {
+                return false;
+            }
+            // Ponderhit: the opponent played the move searched for, so the move is ours to time now
+            self.pondering = None;
+            self.start = Instant::now();
+        }
+        self.budget
+            .is_some_and(|budget| self.start.elapsed() >= limit(budget))
+    }
+
+    /// Searches 1 ply deeper each iteration, trying the previous best move first,
+    /// and calls on_iteration after every completed depth.
+    /// Each iteration expects a score close to the last one and widens its window if wrong.
+    /// Stops at max_depth, when the stop flag is set or when the time budget runs out.
+    /// An iteration cut short is thrown away, but a legal move is always returned.
+    /// Returns None if the side to move has no legal moves.
+    /// With more than one thread in options, helpers search the same position alongside
+    /// until this search ends. Only this search reports iterations and the result.
+    pub(crate) fn iterative_deepening(
+        &mut self,
+        position: &mut BoardPosition,
+        max_depth: u32,
+        on_iteration: &mut dyn FnMut(&SearchResult),
+    ) -> Option<SearchResult> {
+        self.tt.new_search();
+        if self.options.threads <= 1 {
+            return self.deepen(position, max_depth, on_iteration);
+        }
+        // Lazy SMP: the helpers share nothing but the table, where what they find
+        // makes the main search's later iterations quicker
+        let helpers_stop = Arc::new(AtomicBool::new(false));
+        let helper_options = SearchOptions {
+            threads: 1,
+            ..self.options
+        };
+        thread::scope(|scope| {
+            for index in 1..self.options.threads {
+                let mut helper = Searcher::new(helpers_stop.clone(), None, self.tt, helper_options);
+                helper.depth_offset = index as u32 % 2;
+                let main_nodes = self.helper_nodes.clone();
+                helper.main_nodes = Some(main_nodes.clone());
+                let mut helper_position = position.clone();
+                scope.spawn(move || {
+                    helper.deepen(&mut helper_position, max_depth, &mut |_| {});
+                    // The nodes since the last whole interval count too
+                    main_nodes.fetch_add(helper.nodes % STOP_CHECK_INTERVAL, Ordering::Relaxed);
+                });
+            }
+            let result = self.deepen(position, max_depth, on_iteration);
+            helpers_stop.store(true, Ordering::Relaxed);
+            result
+        })
+        // Counted again once every helper has finished
+        .map(|result| SearchResult {
+            nodes: self.total_nodes(),
+            ..result
+        })
+    }
+
+    /// The iterative deepening itself, on this thread alone ///
+    fn deepen(
+        &mut self,
+        position: &mut BoardPosition,
+        max_depth: u32,
+        on_iteration: &mut dyn FnMut(&SearchResult),
+    ) -> Option<SearchResult> {
+        let mut root_moves = position.eval_moves();
+        if root_moves.is_empty() {
+            return None;
+        }
+        let hash_move = self
+            .tt
+            .probe(position.hash)
+            .and_then(|entry| entry.best_move);
+        self.ordering.order(position, &mut root_moves, hash_move, 0);
+        let mut result = SearchResult {
+            score: 0,
+            depth: 0,
+            nodes: 0,
+            pv: vec![root_moves[0]],
+        };
+        for depth in 1 + self.depth_offset..=max_depth {
+            let mut delta = ASPIRATION_WINDOW;
+            let (mut alpha, mut beta) = match depth >= ASPIRATION_MIN_DEPTH {
+                true => (result.score - delta, result.score + delta),
+                false => (-INFINITY, INFINITY),
+            };
+            let (score, best, mut pv) = loop {
+                let (score, best, pv) = self.search_root(position, &root_moves, depth, alpha, beta);
+                if self.stopped {
+                    break (score, best, pv);
+                }
+                // Outside the window the score is only a bound, so search again with a wider one
+                if score <= alpha {
+                    alpha = (score - delta).max(-INFINITY);
+                } else if score >= beta {
+                    beta = (score + delta).min(INFINITY);
+                } else {
+                    break (score, best, pv);
+                }
+                delta *= 2;
+            };
+            if self.stopped {
+                break;
+            }
+            // Search the best move first next iteration so alpha is raised early
+            root_moves.swap(0, best);
+            pv.insert(0, root_moves[0]);
+            self.tt.store(
+                position.hash,
+                depth,
+                0,
+                Bound::Exact,
+                score,
+                Some(root_moves[0]),
+            );
+            result = SearchResult {
+                score,
+                depth,
+                nodes: self.total_nodes(),
+                pv,
+            };
+            on_iteration(&result);
+            // The next iteration takes several times as long, so don't start what can't finish
+            if self.past(|budget| budget.soft) {
+                break;
+            }
+        }
+        result.nodes = self.total_nodes();
+        Some(result)
+    }
+
+    /// Searches every root move within the window alpha to beta. Returns the best score,
+    /// the index of its move in root_moves and the line after that move.
+    /// A score at or outside the window only bounds the real score.
+    fn search_root(
+        &mut self,
+        position: &mut BoardPosition,
+        root_moves: &[Move],
+        depth: u32,
+        mut alpha: i32,
+        beta: i32,
+    ) -> (i32, usize, Vec<Move>) {
+        let mut best = (-INFINITY, 0, Vec::new());
+        for (i, chess_move) in root_moves.iter().enumerate() {
+            let mut child_pv = Vec::new();
+            let undo = position.make_move(chess_move);
+            let score = match i {
+                0 => -self.alpha_beta(position, depth - 1, 1, -beta, -alpha, &mut child_pv),
+                _ => self.scout(position, depth - 1, 1, alpha, beta, &mut child_pv),
+            };
+            position.unmake_move(chess_move, undo);
+            if self.stopped {
+                break;
+            }
+            if score > best.0 {
+                best = (score, i, child_pv);
+            }
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+        best
+    }
+
+    /// Searches the position just reached by a move that is expected to be no better
+    /// than alpha. A null window shows that quickest, and only if the move beats alpha
+    /// after all is it searched again with the full window. Scores are from the mover's point of view.
+    fn scout(
+        &mut self,
+        position: &mut BoardPosition,
+        depth: u32,
+        ply: u32,
+        alpha: i32,
+        beta: i32,
+        pv: &mut Vec<Move>,
+    ) -> i32 {
+        let score = -self.alpha_beta(position, depth, ply, -alpha - 1, -alpha, pv);
+        if score > alpha && score < beta {
+            -self.alpha_beta(position, depth, ply, -beta, -alpha, pv)
+        } else {
+            score
+        }
+    }
+
+    /// Negamax with alpha-beta pruning, scores are from the point of view of the side to move ///
+    fn alpha_beta(
+        &mut self,
+        position: &mut BoardPosition,
+        depth: u32,
+        ply: u32,
+        mut alpha: i32,
+        beta: i32,
+        pv: &mut Vec<Move>,
+    ) -> i32 {
+        if depth == 0 {
+            return self.quiescence(position, ply, alpha, beta);
+        }
+        if self.count_node() {
+            return 0;
+        }
+        if position.is_search_draw() {
+            return draw_score(position, ply);
+        }
+        let original_alpha = alpha;
+        let entry = self.tt.probe(position.hash);
+        if let Some(entry) = entry.filter(|entry| entry.depth >= depth) {
+            let score = entry.score(ply);
+            let cutoff = match entry.bound {
+                Bound::Exact => true,
+                Bound::Lower => score >= beta,
+                Bound::Upper => score <= alpha,
+            };
+            if cutoff {
+                pv.clear();
+                pv.extend(entry.best_move);
+                return score;
+            }
+        }
+        let in_check = position.is_in_check(position.side_to_move);
+        if self.options.null_move
+            && depth >= NULL_MOVE_MIN_DEPTH
+            && !in_check
+            && self.null_move_ply != Some(ply - 1)
+            && beta.abs() < MATE_SCORE - MAX_MATE_PLY
+            // With only pawns left passing may be the best move, so a null move proves nothing
+            && has_pieces(position, position.side_to_move)
+        {
+            let reduction = if depth > 6 { 3 } else { 2 };
+            let previous_null_move = self.null_move_ply.replace(ply);
+            let undo = position.make_null_move();
+            let score = -self.alpha_beta(
+                position,
+                depth.saturating_sub(1 + reduction),
+                ply + 1,
+                -beta,
+                -beta + 1,
+                &mut Vec::new(),
+            );
+            position.unmake_null_move(undo);
+            self.null_move_ply = previous_null_move;
+            // Even passing fails high, so a real move would too
+            if score >= beta && !self.stopped {
+                return beta;
+            }
+        }
+
+        let mut moves = position.eval_moves();
+        if moves.is_empty() {
+            return if in_check {
+                -MATE_SCORE + ply as i32
+            } else {
+                0
+            };
+        }
+        let hash_move = entry.and_then(|entry| entry.best_move);
+        self.ordering.order(position, &mut moves, hash_move, ply);
+        let mut best_move = None;
+        for (i, chess_move) in moves.into_iter().enumerate() {
+            let mut child_pv = Vec::new();
+            let undo = position.make_move(&chess_move);
+            // Quiet moves late in the order rarely turn out best, so they get a shallower
+            // search first and only the full depth if they beat alpha after all
+            let reduce = self.options.late_move_reductions
+                && i >= LMR_FULL_DEPTH_MOVES
+                && depth >= LMR_MIN_DEPTH
+                && !in_check
+                && !chess_move.is_tactical()
+                && !position.is_in_check(position.side_to_move);
+            let mut score = alpha + 1;
+            if reduce {
+                let reduction = if i >= 2 * LMR_FULL_DEPTH_MOVES && depth >= 6 {
+                    2
+                } else {
+                    1
+                };
+                score = -self.alpha_beta(
+                    position,
+                    depth - 1 - reduction,
+                    ply + 1,
+                    -alpha - 1,
+                    -alpha,
+                    &mut child_pv,
+                );
+            }
+            if score > alpha {
+                score = match i {
+                    0 => {
+                        -self.alpha_beta(position, depth - 1, ply + 1, -beta, -alpha, &mut child_pv)
+                    }
+                    _ => self.scout(position, depth - 1, ply + 1, alpha, beta, &mut child_pv),
+                };
+            }
+            position.unmake_move(&chess_move, undo);
+            if score > alpha {
+                alpha = score;
+                best_move = Some(chess_move);
+                pv.clear();
+                pv.push(chess_move);
+                pv.append(&mut child_pv);
+                if alpha >= beta {
+                    if !chess_move.is_tactical() {
+                        self.ordering.add_cutoff(position, &chess_move, depth, ply);
+                    }
+                    break;
+                }
+            }
+        }
+        if !self.stopped {
+            let bound = if alpha >= beta {
+                Bound::Lower
+            } else if alpha > original_alpha {
+                Bound::Exact
+            } else {
+                Bound::Upper
+            };
+            // Keep the earlier best move for ordering if nothing beat alpha this time
+            let best_move = best_move.or(hash_move);
+            self.tt
+                .store(position.hash, depth, ply, bound, alpha, best_move);
+        }
+        alpha
+    }
+
+    /// Keeps searching captures and promotions past the depth limit, so the evaluation
+    /// isn't taken halfway through an exchange. The side to move may also stand pat,
+    /// taking the evaluation as it is, since it doesn't have to capture.
+    fn quiescence(
+        &mut self,
+        position: &mut BoardPosition,
+        ply: u32,
+        mut alpha: i32,
+        beta: i32,
+    ) -> i32 {
+        if self.count_node() {
+            return 0;
+        }
+        if position.is_search_draw() {
+            return draw_score(position, ply);
+        }
+        let legal_moves = position.eval_moves();
+        let in_check = position.is_in_check(position.side_to_move);
+        if legal_moves.is_empty() {
+            return if in_check {
+                -MATE_SCORE + ply as i32
+            } else {
+                0
+            };
+        }
+        // In check there's no standing pat, every evasion is searched
+        let mut moves: Vec<Move> = if in_check {
+            legal_moves
+        } else {
+            let stand_pat = position.eval(position.side_to_move, &self.options.eval);
+            if stand_pat >= beta {
+                return stand_pat;
+            }
+            alpha = alpha.max(stand_pat);
+            legal_moves.into_iter().filter(Move::is_tactical).collect()
+        };
+        moves.sort_by_cached_key(|chess_move| Reverse(mvv_lva(position, chess_move)));
+        for chess_move in moves {
+            let undo = position.make_move(&chess_move);
+            let score = -self.quiescence(position, ply + 1, -beta, -alpha);
+            position.unmake_move(&chess_move, undo);
+            if score > alpha {
+                alpha = score;
+                if alpha >= beta {
+                    break;
+                }
+            }
+        }
+        alpha
+    }
+}
+
+/// Score of a position the search counts as drawn, unless the move into it was checkmate,
+/// which wins even with fifty moves or a repetition behind it ///
+fn draw_score(position: &mut BoardPosition, ply: u32) -> i32 {
+    if position.is_in_check(position.side_to_move) && position.eval_moves().is_empty() {
+        -MATE_SCORE + ply as i32
+    } else {
+        0
+    }
+}
+
+/// Whether color has anything besides its king and pawns ///
+fn has_pieces(position: &BoardPosition, color: PieceColor) -> bool {
+    [Knight, Bishop, Rook { has_moved: true }, Queen]
+        .into_iter()
+        .any(|piece_type| position.bitboards.pieces(color, piece_type) != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transposition::DEFAULT_SIZE_MB;
+
+    fn search(fen: &str, depth: u32, options: SearchOptions) -> SearchResult {
+        let mut position = BoardPosition::from_fen(fen).unwrap();
+        let tt = TranspositionTable::new(DEFAULT_SIZE_MB);
+        Searcher::new(Arc::new(AtomicBool::new(false)), None, &tt, options)
+            .iterative_deepening(&mut position, depth, &mut |_| {})
+            .unwrap()
+    }
+
+    #[test]
+    fn quiescence_sees_the_recapture() {
+        // Qxd5 wins a pawn at depth 1 but exd5 takes the queen straight back
+        let result = search(
+            "4k3/8/4p3/3p4/8/8/8/3QK3 w - - 0 1",
+            1,
+            SearchOptions::default(),
+        );
+        assert_ne!(result.pv[0].to_string(), "d1d5");
+        assert!(result.score > 0);
+
+        // A pawn that isn't defended can be taken
+        let result = search(
+            "4k3/8/8/3p4/8/8/8/3QK3 w - - 0 1",
+            1,
+            SearchOptions::default(),
+        );
+        assert_eq!(result.pv[0].to_string(), "d1d5");
+    }
+
+    #[test]
+    fn mate_in_one_at_depth_one() {
+        // Ra8 is only mate once quiescence searches the evasions and finds none
+        let result = search(
+            "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1",
+            1,
+            SearchOptions::default(),
+        );
+        assert_eq!(result.pv[0].to_string(), "a1a8");
+        assert_eq!(result.mate_in(), Some(1));
+    }
+
+    #[test]
+    fn finds_mate() {
+        let result = search(
+            "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1",
+            3,
+            SearchOptions::default(),
+        );
+        assert_eq!(result.pv[0].to_string(), "a1a8");
+        assert_eq!(result.mate_in(), Some(1));
+    }
+
+    #[test]
+    fn mate_beats_the_fifty_move_rule() {
+        // Ra8# is the hundredth move without a capture or pawn move
+        let result = search(
+            "6k1/5ppp/8/8/8/8/8/R5K1 w - - 99 80",
+            3,
+            SearchOptions::default(),
+        );
+        assert_eq!(result.pv[0].to_string(), "a1a8");
+        assert_eq!(result.mate_in(), Some(1));
+    }
+
+    #[test]
+    fn quiet_sacrifice() {
+        // Ra6 bxa6 b7# gives the rook away without a check. Black is then in zugzwang,
+        // which null-move pruning can't see, since passing would be safe
+        let options = SearchOptions {
+            null_move: false,
+            ..SearchOptions::default()
+        };
+        let result = search("kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1", 4, options);
+        assert_eq!(result.pv[0].to_string(), "a1a6");
+        assert_eq!(result.mate_in(), Some(2));
+    }
+
+    /// Negamax without any pruning, ending in the same quiescence search ///
+    fn minimax(searcher: &mut Searcher, position: &mut BoardPosition, depth: u32, ply: u32) -> i32 {
+        if depth == 0 {
+            return searcher.quiescence(position, ply, -INFINITY, INFINITY);
+        }
+        if position.is_search_draw() {
+            return draw_score(position, ply);
+        }
+        let moves = position.eval_moves();
+        if moves.is_empty() {
+            return match position.is_in_check(position.side_to_move) {
+                true => -MATE_SCORE + ply as i32,
+                false => 0,
+            };
+        }
+        moves
+            .iter()
+            .map(|chess_move| {
+                let undo = position.make_move(chess_move);
+                let score = -minimax(searcher, position, depth - 1, ply + 1);
+                position.unmake_move(chess_move, undo);
+                score
+            })
+            .max()
+            .unwrap()
+    }
+
+    #[test]
+    fn alpha_beta_matches_minimax() {
+        // Without the unsound pruning the cutoffs only skip moves that can't change the score
+        let options = SearchOptions {
+            null_move: false,
+            late_move_reductions: false,
+            ..SearchOptions::default()
+        };
+        for fen in [
+            "4k3/p7/2n5/3r4/8/2N5/1P6/3RK3 w - - 0 1",
+            "r3k3/8/4b3/4p3/2B1P3/2N5/8/6K1 b q - 0 1",
+        ] {
+            let mut position = BoardPosition::from_fen(fen).unwrap();
+            let tt = TranspositionTable::new(1);
+            let mut searcher = Searcher::new(Arc::new(AtomicBool::new(false)), None, &tt, options);
+            let expected = minimax(&mut searcher, &mut position, 3, 0);
+            assert_eq!(search(fen, 3, options).score, expected, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn aspiration_windows_widen() {
+        let fen = "rnbqkbnr/ppp2ppp/8/3pp3/2B1P3/8/PPPP1PPP/RNBQK1NR w KQkq - 0 3";
+        let options = SearchOptions {
+            null_move: false,
+            late_move_reductions: false,
+            ..SearchOptions::default()
+        };
+        let mut position = BoardPosition::from_fen(fen).unwrap();
+        let tt = TranspositionTable::new(DEFAULT_SIZE_MB);
+        let mut iterations = Vec::new();
+        Searcher::new(Arc::new(AtomicBool::new(false)), None, &tt, options).iterative_deepening(
+            &mut position,
+            5,
+            &mut |result| iterations.push(result.clone()),
+        );
+        let [.., third, fourth, fifth] = &iterations[..] else {
+            panic!("only {} iterations", iterations.len());
+        };
+        // Depth 4 fails low out of the window around depth 3's score, and depth 5 fails high
+        assert!(fourth.score < third.score - ASPIRATION_WINDOW);
+        assert!(fifth.score > fourth.score + ASPIRATION_WINDOW);
+        // The new best move was searched after the old one, so it beat a null window first
+        assert_ne!(fourth.pv[0], third.pv[0]);
+
+        for result in [fourth, fifth] {
+            let tt = TranspositionTable::new(DEFAULT_SIZE_MB);
+            let mut searcher = Searcher::new(Arc::new(AtomicBool::new(false)), None, &tt, options);
+            let mut moves = position.eval_moves();
+            searcher.ordering.order(&position, &mut moves, None, 0);
+            let (score, best, _) =
+                searcher.search_root(&mut position, &moves, result.depth, -INFINITY, INFINITY);
+            assert_eq!((result.score, result.pv[0]), (score, moves[best]));
+        }
+    }
+
+    #[test]
+    fn pruning_keeps_tactics() {
+        // Rd8+ Rxd8 Rxd8# is found with or without the pruning
+        let fen = "2r3k1/5ppp/8/8/8/8/3R1PPP/3R2K1 w - - 0 1";
+        for pruning in [true, false] {
+            let options = SearchOptions {
+                null_move: pruning,
+                late_move_reductions: pruning,
+                ..SearchOptions::default()
+            };
+            let result = search(fen, 5, options);
+            assert_eq!(result.pv[0].to_string(), "d2d8");
+            assert_eq!(result.mate_in(), Some(2));
+        }
+    }
+
+    #[test]
+    fn helper_threads() {
+        // The helpers share the table with the main search but don't change its answer
+        let fen = "2r3k1/5ppp/8/8/8/8/3R1PPP/3R2K1 w - - 0 1";
+        let options = SearchOptions {
+            threads: 4,
+            ..SearchOptions::default()
+        };
+        let mut position = BoardPosition::from_fen(fen).unwrap();
+        let tt = TranspositionTable::new(DEFAULT_SIZE_MB);
+        let mut searcher = Searcher::new(Arc::new(AtomicBool::new(false)), None, &tt, options);
+        let result = searcher
+            .iterative_deepening(&mut position, 5, &mut |_| {})
+            .unwrap();
+        assert_eq!(result.depth, 5);
+        assert_eq!(result.pv[0].to_string(), "d2d8");
+        assert_eq!(result.mate_in(), Some(2));
+        // The helpers did search, and their nodes are counted in the result
+        let helper_nodes = searcher.helper_nodes.load(Ordering::Relaxed);
+        assert!(helper_nodes > 0);
+        assert_eq!(result.nodes, searcher.nodes + helper_nodes);
+    }
+
+    #[test]
+    fn null_move() {
+        let fen = "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1";
+        let mut position = BoardPosition::from_fen(fen).unwrap();
+        let undo = position.make_null_move();
+        assert_eq!(position.side_to_move, PieceColor::Black);
+        assert_eq!(position.hash, position.zobrist_hash());
+        position.unmake_null_move(undo);
+        assert_eq!(position.to_fen(), fen);
+
+        // Kings and pawns only, where passing could be better than any move
+        assert!(!has_pieces(&position, PieceColor::White));
+        let position = BoardPosition::from_fen("4k3/8/8/8/8/8/4P3/2N1K3 w - - 0 1").unwrap();
+        assert!(has_pieces(&position, PieceColor::White));
+        assert!(!has_pieces(&position, PieceColor::Black));
+    }
+}
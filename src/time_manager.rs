@@ -0,0 +1,111 @@
+use std::time::Duration;
+
+/// Kept back from every allocation for the time spent passing the move along ///
+const MOVE_OVERHEAD: Duration = Duration::from_millis(50);
+/// Without movestogo, assume this many moves still have to be played on the clock ///
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+
+/// How long the bot may think, either a fixed time per move or a game clock ///
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct TimeControl {
+    pub(crate) movetime: Option<Duration>,
+    /// Time left on the bot's own clock ///
+    pub(crate) remaining: Option<Duration>,
+    /// Added to the clock after every move ///
+    pub(crate) increment: Duration,
+    /// Moves until the next time control, if the clock has one ///
+    pub(crate) moves_to_go: Option<u32>,
+}
+
+/// Time allocated to a single move ///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct TimeBudget {
+    /// No new iteration is started once this much time has passed ///
+    pub(crate) soft: Duration,
+    /// The search is aborted once this much time has passed ///
+    pub(crate) hard: Duration,
+}
+
+impl TimeControl {
+    pub(crate) fn fixed(movetime: Duration) -> TimeControl {
+        TimeControl {
+            movetime: Some(movetime),
+            ..TimeControl::default()
+        }
+    }
+
+    pub(crate) fn clock(remaining: Duration, increment: Duration) -> TimeControl {
+        TimeControl {
+            remaining: Some(remaining),
+            increment,
+            ..TimeControl::default()
+        }
+    }
+
+    /// Returns None when there is no time limit at all ///
+    pub(crate) fn budget(&self) -> Option<TimeBudget> {
+        if let Some(movetime) = self.movetime {
+            let hard = movetime
+                .saturating_sub(MOVE_OVERHEAD)
+                .max(Duration::from_millis(1));
+            return Some(TimeBudget { soft: hard, hard });
+        }
+        let usable = self.remaining?.saturating_sub(MOVE_OVERHEAD);
+        let moves_to_go = self.moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+        // Never plan to use more than half the clock on a single move
+        let soft = (usable / moves_to_go + self.increment * 3 / 4).min(usable / 2);
+        let hard = (soft * 3).min(usable / 2);
+        Some(TimeBudget { soft, hard })
+    }
+
+    /// Moves the clock on after a move that took elapsed ///
+    pub(crate) fn spend(&mut self, elapsed: Duration) {
+        if let Some(remaining) = &mut self.remaining {
+            *remaining = remaining.saturating_sub(elapsed) + self.increment;
+        }
+        if let Some(moves_to_go) = &mut self.moves_to_go {
+            *moves_to_go = moves_to_go.saturating_sub(1).max(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn budgets() {
+        assert_eq!(TimeControl::default().budget(), None);
+
+        let fixed = TimeControl::fixed(Duration::from_secs(2)).budget().unwrap();
+        assert_eq!(fixed.soft, fixed.hard);
+        assert_eq!(fixed.hard, Duration::from_millis(1950));
+
+        let clock = TimeControl::clock(Duration::from_millis(60050), Duration::ZERO)
+            .budget()
+            .unwrap();
+        assert_eq!(clock.soft, Duration::from_secs(2));
+        assert_eq!(clock.hard, Duration::from_secs(6));
+
+        let increment = TimeControl::clock(Duration::from_millis(60050), Duration::from_secs(4))
+            .budget()
+            .unwrap();
+        assert_eq!(increment.soft, Duration::from_secs(5));
+
+        // A nearly flagged clock never allocates more than half of what is left
+        let low = TimeControl::clock(Duration::from_millis(250), Duration::from_secs(2))
+            .budget()
+            .unwrap();
+        assert_eq!(low.soft, Duration::from_millis(100));
+        assert_eq!(low.hard, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn spend_adds_increment() {
+        let mut control = TimeControl::clock(Duration::from_secs(10), Duration::from_secs(1));
+        control.spend(Duration::from_secs(3));
+        assert_eq!(control.remaining, Some(Duration::from_secs(8)));
+        control.spend(Duration::from_secs(30));
+        assert_eq!(control.remaining, Some(Duration::from_secs(1)));
+    }
+}
@@ -0,0 +1,291 @@
+use std::mem::size_of;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+use crate::bitboard::square_index;
+use crate::search::{MATE_SCORE, MAX_MATE_PLY};
+use crate::{CoordinateSet, Move, MoveType, MoveType::*, PieceType};
+
+/// Size of the table the bot and the UCI front-end search with ///
+pub(crate) const DEFAULT_SIZE_MB: usize = 16;
+
+/// How a stored score relates to the real score of the position ///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Bound {
+    Exact,
+    /// The search failed high, the real score is at least this ///
+    Lower,
+    /// The search failed low, the real score is at most this ///
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Entry {
+    /// Full hash of the position, since many positions share a slot ///
+    key: u64,
+    pub(crate) depth: u32,
+    pub(crate) bound: Bound,
+    score: i32,
+    /// Best move found, or an earlier one when every move failed low ///
+    pub(crate) best_move: Option<Move>,
+    /// The search that stored it, counting up and wrapping around ///
+    generation: u8,
+}
+
+impl Entry {
+    /// The stored score with mates counted from ply instead of from the stored position ///
+    pub(crate) fn score(&self, ply: u32) -> i32 {
+        if self.score > MATE_SCORE - MAX_MATE_PLY {
+            self.score - ply as i32
+        } else if self.score < -MATE_SCORE + MAX_MATE_PLY {
+            self.score + ply as i32
+        } else {
+            self.score
+        }
+    }
+}
+
+/// The kinds a Move can have, a stored move keeps the index of its kind ///
+const MOVE_KINDS: [MoveType; 7] = [
+    NoCapture,
+    CaptureOnly,
+    PawnFirst,
+    EnPassante,
+    Castle,
+    Promotion,
+    PromotionCapture,
+];
+
+/// 20 bits: 6 for each square, 3 for the promotion and 4 for the kind, 0 is no move ///
+fn pack_move(chess_move: Option<Move>) -> u64 {
+    let Some(chess_move) = chess_move else {
+        return 0;
+    };
+    let promotion = chess_move.promotion.map_or(0, |piece_type| {
+        1 + PieceType::to_promote()
+            .iter()
+            .position(|&promoted| promoted == piece_type)
+            .unwrap()
+    });
+    let kind = MOVE_KINDS
+        .iter()
+        .position(|&kind| kind == chess_move.kind)
+        .unwrap();
+    square_index(&chess_move.from) as u64
+        | (square_index(&chess_move.to) as u64) << 6
+        | (promotion as u64) << 12
+        | (kind as u64 + 1) << 15
+}
+
+fn unpack_move(bits: u64) -> Option<Move> {
+    let square = |index: u64| CoordinateSet::new((index % 8) as i32, (index / 8) as i32);
+    let kind = (bits >> 15) & 0xF;
+    if kind == 0 {
+        return None;
+    }
+    let promotion = (bits >> 12) & 0x7;
+    Some(Move {
+        from: square(bits & 0x3F),
+        to: square((bits >> 6) & 0x3F),
+        promotion: match promotion {
+            0 => None,
+            _ => Some(PieceType::to_promote()[promotion as usize - 1]),
+        },
+        kind: MOVE_KINDS[kind as usize - 1],
+    })
+}
+
+impl Entry {
+    /// Everything but the key in one word: the score in the low 22 bits, which fit every
+    /// mate score, then the depth, the bound, the generation and the move.
+    /// The bound is never 0, so an empty slot reads as no entry.
+    fn pack(&self) -> u64 {
+        let bound: u64 = match self.bound {
+            Bound::Exact => 1,
+            Bound::Lower => 2,
+            Bound::Upper => 3,
+        };
+        (self.score as u64 & 0x3F_FFFF)
+            | (self.depth.min(0xFF) as u64) << 22
+            | bound << 30
+            | (self.generation as u64) << 32
+            | pack_move(self.best_move) << 40
+    }
+
+    fn unpack(key: u64, data: u64) -> Option<Entry> {
+        let bound = match (data >> 30) & 0x3 {
+            1 => Bound::Exact,
+            2 => Bound::Lower,
+            3 => Bound::Upper,
+            _ => return None,
+        };
+        Some(Entry {
+            key,
+            depth: ((data >> 22) & 0xFF) as u32,
+            bound,
+            // Shifted up and back down so the sign is carried back in
+            score: ((data << 42) as i64 >> 42) as i32,
+            best_move: unpack_move(data >> 40),
+            generation: (data >> 32) as u8,
+        })
+    }
+}
+
+/// One entry stored as two words, the key is kept XORed with the data so a slot
+/// half written by another thread doesn't match either position ///
+#[derive(Default)]
+struct Slot {
+    check: AtomicU64,
+    data: AtomicU64,
+}
+
+/// Search results by Zobrist hash, so positions reached by different move orders are only searched once.
+/// Every search thread shares one table without locking it.
+pub(crate) struct TranspositionTable {
+    entries: Vec<Slot>,
+    /// Goes up by one every search, so entries left from earlier moves can be told apart ///
+    generation: AtomicU8,
+}
+
+impl TranspositionTable {
+    /// A table using about size_mb megabytes, rounded down to a power of two entries ///
+    pub(crate) fn new(size_mb: usize) -> TranspositionTable {
+        let wanted = (size_mb * 1024 * 1024 / size_of::<Slot>()).max(1);
+        let len = 1 << wanted.ilog2();
+        TranspositionTable {
+            entries: (0..len).map(|_| Slot::default()).collect(),
+            generation: AtomicU8::new(0),
+        }
+    }
+
+    /// Called when a search starts, after that entries stored before it give way to new ones ///
+    pub(crate) fn new_search(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn slot(&self, key: u64) -> usize {
+        key as usize & (self.entries.len() - 1)
+    }
+
+    pub(crate) fn probe(&self, key: u64) -> Option<Entry> {
+        let slot = &self.entries[self.slot(key)];
+        let data = slot.data.load(Ordering::Relaxed);
+        if slot.check.load(Ordering::Relaxed) ^ data != key {
+            return None;
+        }
+        Entry::unpack(key, data)
+    }
+
+    /// Stores a score found at ply from the root. A different position already in the slot
+    /// is only kept if this search stored it and searched it deeper,
+    /// the same position is always replaced.
+    pub(crate) fn store(
+        &self,
+        key: u64,
+        depth: u32,
+        ply: u32,
+        bound: Bound,
+        score: i32,
+        best_move: Option<Move>,
+    ) {
+        let slot = &self.entries[self.slot(key)];
+        let generation = self.generation.load(Ordering::Relaxed);
+        let old_data = slot.data.load(Ordering::Relaxed);
+        let old_key = slot.check.load(Ordering::Relaxed) ^ old_data;
+        if let Some(old) = Entry::unpack(old_key, old_data) {
+            if old.key != key && old.generation == generation && old.depth > depth {
+                return;
+            }
+        }
+        // Mates are stored counted from this position so they stay right wherever it is reached
+        let score = if score > MATE_SCORE - MAX_MATE_PLY {
+            score + ply as i32
+        } else if score < -MATE_SCORE + MAX_MATE_PLY {
+            score - ply as i32
+        } else {
+            score
+        };
+        let data = Entry {
+            key,
+            depth,
+            bound,
+            score,
+            best_move,
+            generation,
+        }
+        .pack();
+        slot.check.store(key ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+
+    pub(crate) fn clear(&self) {
+        for slot in &self.entries {
+            slot.check.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::STARTING_FEN;
+    use crate::BoardPosition;
+
+    #[test]
+    fn store_and_probe() {
+        let mut position = BoardPosition::from_fen(STARTING_FEN).unwrap();
+        let best = position.parse_long_algebraic("e2e4").unwrap();
+        let table = TranspositionTable::new(1);
+        assert!(table.entries.len().is_power_of_two());
+        assert_eq!(table.probe(position.hash), None);
+
+        table.store(position.hash, 4, 0, Bound::Exact, 30, Some(best));
+        let entry = table.probe(position.hash).unwrap();
+        assert_eq!(
+            (entry.depth, entry.bound, entry.score(0)),
+            (4, Bound::Exact, 30)
+        );
+        assert_eq!(entry.best_move, Some(best));
+
+        // A shallower result for a different position in the same slot doesn't push it out
+        let other = position.hash + table.entries.len() as u64;
+        table.store(other, 2, 0, Bound::Lower, 10, None);
+        assert_eq!(table.probe(other), None);
+        assert!(table.probe(position.hash).is_some());
+        // In a later search it does, the deep entry is left over from an earlier move
+        table.new_search();
+        table.store(other, 2, 0, Bound::Lower, 10, None);
+        assert!(table.probe(other).is_some());
+        assert_eq!(table.probe(position.hash), None);
+
+        table.clear();
+        assert_eq!(table.probe(other), None);
+    }
+
+    #[test]
+    fn mate_scores_follow_the_ply() {
+        let table = TranspositionTable::new(1);
+        // Mate in 3 plies found 5 plies from the root is mate in 3 from the stored position
+        table.store(7, 3, 5, Bound::Exact, MATE_SCORE - 8, None);
+        assert_eq!(table.probe(7).unwrap().score(1), MATE_SCORE - 4);
+        table.store(7, 3, 5, Bound::Exact, -MATE_SCORE + 8, None);
+        assert_eq!(table.probe(7).unwrap().score(1), -MATE_SCORE + 4);
+    }
+
+    #[test]
+    fn moves_survive_packing() {
+        let mut position =
+            BoardPosition::from_fen("r3k2r/1P6/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1").unwrap();
+        let table = TranspositionTable::new(1);
+        for (key, text) in ["e1g1", "e1c1", "e5d6", "b7a8n", "b7b8q", "a1a8", "e1d1"]
+            .into_iter()
+            .enumerate()
+        {
+            let chess_move = position.parse_long_algebraic(text).unwrap();
+            table.store(key as u64, 1, 0, Bound::Upper, -5, Some(chess_move));
+            let entry = table.probe(key as u64).unwrap();
+            assert_eq!(entry.best_move, Some(chess_move), "{}", text);
+            assert_eq!(entry.score(0), -5);
+        }
+    }
+}
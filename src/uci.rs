@@ -0,0 +1,321 @@
+use std::io::{stdin, BufRead};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::fen::STARTING_FEN;
+use crate::search::{SearchOptions, SearchResult, Searcher, MAX_DEPTH, MAX_THREADS};
+use crate::time_manager::TimeControl;
+use crate::transposition::{TranspositionTable, DEFAULT_SIZE_MB};
+use crate::{BoardPosition, Move, PieceColor};
+
+/// Picks out the field of SearchOptions an option sets ///
+type CheckOption = fn(&mut SearchOptions) -> &mut bool;
+/// Check options turning search features and evaluation terms on and off ///
+const CHECK_OPTIONS: [(&str, CheckOption); 6] = [
+    ("NullMove", |options| &mut options.null_move),
+    ("LateMoveReductions", |options| {
+        &mut options.late_move_reductions
+    }),
+    ("PieceSquares", |options| &mut options.eval.piece_squares),
+    ("PawnStructure", |options| &mut options.eval.pawn_structure),
+    ("Mobility", |options| &mut options.eval.mobility),
+    ("KingSafety", |options| &mut options.eval.king_safety),
+];
+
+/// Limits given to a go command ///
+#[derive(Debug, Default, PartialEq)]
+struct GoLimits {
+    depth: Option<u32>,
+    time: TimeControl,
+    /// Keep searching until told to stop, even when the search is finished ///
+    infinite: bool,
+}
+
+/// A search running on its own thread, which prints bestmove when it ends ///
+struct RunningSearch {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+impl RunningSearch {
+    fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.handle.join().unwrap();
+    }
+}
+
+/// Talks the Universal Chess Interface over stdin and stdout until quit ///
+pub(crate) fn run() {
+    let mut position = BoardPosition::from_fen(STARTING_FEN).unwrap();
+    let mut search: Option<RunningSearch> = None;
+    let mut options = SearchOptions::default();
+    let tt = Arc::new(TranspositionTable::new(DEFAULT_SIZE_MB));
+    for line in stdin().lock().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let Some((&command, arguments)) = tokens.split_first() else {
+            continue;
+        };
+        match command {
+            "uci" => {
+                println!("id name Rook Bot");
+                println!("id author 3TH3R3AL");
+                for (name, _) in CHECK_OPTIONS {
+                    println!("option name {} type check default true", name);
+                }
+                println!(
+                    "option name Threads type spin default 1 min 1 max {}",
+                    MAX_THREADS
+                );
+                println!("uciok");
+            }
+            "isready" => println!("readyok"),
+            "setoption" => {
+                if let Err(e) = set_option(&mut options, arguments) {
+                    println!("info string {}", e);
+                }
+            }
+            "ucinewgame" => {
+                if let Some(running) = search.take() {
+                    running.stop();
+                }
+                position = BoardPosition::from_fen(STARTING_FEN).unwrap();
+                tt.clear();
+            }
+            "position" => {
+                if let Some(running) = search.take() {
+                    running.stop();
+                }
+                match parse_position(arguments) {
+                    Ok(parsed) => position = parsed,
+                    Err(e) => println!("info string {}", e),
+                }
+            }
+            "go" => {
+                if let Some(running) = search.take() {
+                    running.stop();
+                }
+                let limits = parse_go(arguments, position.side_to_move);
+                search = Some(start_search(position.clone(), limits, tt.clone(), options));
+            }
+            "stop" => {
+                if let Some(running) = search.take() {
+                    running.stop();
+                }
+            }
+            "quit" => break,
+            // The protocol says unknown commands are ignored
+            _ => {}
+        }
+    }
+    if let Some(running) = search.take() {
+        running.stop();
+    }
+}
+
+/// Parses the arguments of "setoption name <name> value <value>", option names ignore case ///
+fn set_option(options: &mut SearchOptions, arguments: &[&str]) -> Result<(), String> {
+    let value_index = arguments
+        .iter()
+        .position(|&token| token == "value")
+        .unwrap_or(arguments.len());
+    let name = match arguments.first() {
+        Some(&"name") => arguments[1..value_index].join(" "),
+        _ => return Err(String::from("setoption needs a name")),
+    };
+    let value = arguments
+        .get(value_index + 1..)
+        .map(|value| value.join(" "))
+        .unwrap_or_default();
+    if name.eq_ignore_ascii_case("Threads") {
+        options.threads = match value.parse() {
+            Ok(threads) if (1..=MAX_THREADS).contains(&threads) => threads,
+            _ => return Err(format!("Threads needs a number from 1 to {}", MAX_THREADS)),
+        };
+        return Ok(());
+    }
+    let (_, field) = CHECK_OPTIONS
+        .iter()
+        .find(|(option, _)| option.eq_ignore_ascii_case(&name))
+        .ok_or_else(|| format!("Unknown option '{}'", name))?;
+    *field(options) = match value.as_str() {
+        "true" => true,
+        "false" => false,
+        _ => return Err(format!("Option '{}' needs true or false", name)),
+    };
+    Ok(())
+}
+
+/// Parses the arguments of "position startpos|fen <fen> [moves <move>...]" ///
+fn parse_position(arguments: &[&str]) -> Result<BoardPosition, String> {
+    let moves_index = arguments
+        .iter()
+        .position(|&token| token == "moves")
+        .unwrap_or(arguments.len());
+    let mut position = match arguments.first() {
+        Some(&"startpos") => BoardPosition::from_fen(STARTING_FEN).unwrap(),
+        Some(&"fen") => BoardPosition::from_fen(&arguments[1..moves_index].join(" "))?,
+        _ => return Err(String::from("position needs startpos or fen")),
+    };
+    for &move_text in arguments.iter().skip(moves_index + 1) {
+        let chess_move = position
+            .parse_long_algebraic(move_text)
+            .map_err(|e| format!("In position command: {}", e))?;
+        position.make_move(&chess_move);
+    }
+    Ok(position)
+}
+
+/// Parses the arguments of "go", only the clock of the side to move matters ///
+fn parse_go(arguments: &[&str], side_to_move: PieceColor) -> GoLimits {
+    let mut limits = GoLimits::default();
+    let mut tokens = arguments.iter();
+    while let Some(&token) = tokens.next() {
+        let mut value = || tokens.next().and_then(|value| value.parse::<u64>().ok());
+        match (token, side_to_move) {
+            ("depth", _) => limits.depth = value().map(|depth| depth as u32),
+            ("movetime", _) => limits.time.movetime = value().map(Duration::from_millis),
+            ("wtime", PieceColor::White) | ("btime", PieceColor::Black) => {
+                limits.time.remaining = value().map(Duration::from_millis)
+            }
+            ("winc", PieceColor::White) | ("binc", PieceColor::Black) => {
+                limits.time.increment = Duration::from_millis(value().unwrap_or(0))
+            }
+            ("movestogo", _) => limits.time.moves_to_go = value().map(|moves| moves as u32),
+            ("infinite", _) => limits.infinite = true,
+            _ => {}
+        }
+    }
+    limits
+}
+
+fn start_search(
+    mut position: BoardPosition,
+    limits: GoLimits,
+    tt: Arc<TranspositionTable>,
+    options: SearchOptions,
+) -> RunningSearch {
+    let stop = Arc::new(AtomicBool::new(false));
+    let search_stop = stop.clone();
+    let handle = thread::spawn(move || {
+        let start = Instant::now();
+        let budget = if limits.infinite {
+            None
+        } else {
+            limits.time.budget()
+        };
+        let max_depth = limits.depth.unwrap_or(MAX_DEPTH);
+        let result = Searcher::new(search_stop.clone(), budget, &tt, options).iterative_deepening(
+            &mut position,
+            max_depth,
+            &mut |result| println!("{}", info_line(result, start.elapsed())),
+        );
+        // Under go infinite bestmove may only be sent after stop
+        while limits.infinite && !search_stop.load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_millis(1));
+        }
+        match result {
+            Some(result) => println!("bestmove {}", result.pv[0]),
+            None => println!("bestmove 0000"),
+        }
+    });
+    RunningSearch { stop, handle }
+}
+
+fn info_line(result: &SearchResult, elapsed: Duration) -> String {
+    let score = match result.mate_in() {
+        Some(moves) => format!("mate {}", moves),
+        None => format!("cp {}", result.score),
+    };
+    let millis = elapsed.as_millis().max(1);
+    format!(
+        "info depth {} score {} nodes {} nps {} time {} pv {}",
+        result.depth,
+        score,
+        result.nodes,
+        result.nodes as u128 * 1000 / millis,
+        millis,
+        result
+            .pv
+            .iter()
+            .map(Move::to_string)
+            .collect::<Vec<String>>()
+            .join(" ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::EvalTerms;
+
+    #[test]
+    fn position_with_moves() {
+        let position = parse_position(&["startpos", "moves", "e2e4", "e7e5", "g1f3"]).unwrap();
+        assert_eq!(
+            position.to_fen(),
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+        );
+        let fen = "8/P7/8/8/8/8/8/k6K w - - 0 1";
+        let mut arguments: Vec<&str> = vec!["fen"];
+        arguments.extend(fen.split_whitespace());
+        arguments.extend(["moves", "a7a8q"]);
+        assert_eq!(
+            parse_position(&arguments).unwrap().to_fen(),
+            "Q7/8/8/8/8/8/8/k6K b - - 0 1"
+        );
+        assert!(parse_position(&["startpos", "moves", "e2e5"]).is_err());
+    }
+
+    #[test]
+    fn go_limits() {
+        assert_eq!(
+            parse_go(&["depth", "4"], PieceColor::White),
+            GoLimits {
+                depth: Some(4),
+                ..GoLimits::default()
+            }
+        );
+        let clock = [
+            "wtime", "60000", "btime", "3000", "winc", "0", "binc", "500",
+        ];
+        assert_eq!(
+            parse_go(&clock, PieceColor::Black).time,
+            TimeControl::clock(Duration::from_secs(3), Duration::from_millis(500))
+        );
+        assert_eq!(
+            parse_go(&["movetime", "250"], PieceColor::White).time,
+            TimeControl::fixed(Duration::from_millis(250))
+        );
+        assert!(parse_go(&["infinite"], PieceColor::White).infinite);
+    }
+
+    #[test]
+    fn options() {
+        let mut options = SearchOptions::default();
+        set_option(&mut options, &["name", "mobility", "value", "false"]).unwrap();
+        set_option(&mut options, &["name", "KingSafety", "value", "false"]).unwrap();
+        set_option(&mut options, &["name", "NullMove", "value", "false"]).unwrap();
+        set_option(&mut options, &["name", "Threads", "value", "4"]).unwrap();
+        assert_eq!(
+            options,
+            SearchOptions {
+                eval: EvalTerms {
+                    mobility: false,
+                    king_safety: false,
+                    ..EvalTerms::default()
+                },
+                null_move: false,
+                threads: 4,
+                ..SearchOptions::default()
+            }
+        );
+        assert!(set_option(&mut options, &["name", "Mobility"]).is_err());
+        assert!(set_option(&mut options, &["name", "Contempt", "value", "10"]).is_err());
+        assert!(set_option(&mut options, &["name", "Threads", "value", "0"]).is_err());
+    }
+}
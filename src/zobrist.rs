@@ -0,0 +1,151 @@
+use std::sync::OnceLock;
+
+use crate::bitboard::{color_index, piece_index, square_index};
+use crate::{BoardPosition, CoordinateSet, Piece, PieceColor, PieceType::*};
+
+/// A random number for every feature of a position, a position's hash xors together
+/// the numbers of the features it has ///
+struct Keys {
+    /// Indexed by color, piece type and square ///
+    pieces: [[[u64; 64]; 6]; 2],
+    /// In KQkq order like castling_rights ///
+    castling: [u64; 4],
+    /// By the file of the pawn that can be taken en passant ///
+    en_passant: [u64; 8],
+    black_to_move: u64,
+}
+
+fn keys() -> &'static Keys {
+    static KEYS: OnceLock<Keys> = OnceLock::new();
+    KEYS.get_or_init(|| {
+        // xorshift64 with a fixed seed, so hashes are the same on every run
+        let mut state: u64 = 0x9E3779B97F4A7C15;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        let mut keys = Keys {
+            pieces: [[[0; 64]; 6]; 2],
+            castling: [0; 4],
+            en_passant: [0; 8],
+            black_to_move: 0,
+        };
+        for key in keys.pieces.iter_mut().flatten().flatten() {
+            *key = next();
+        }
+        for key in keys.castling.iter_mut().chain(keys.en_passant.iter_mut()) {
+            *key = next();
+        }
+        keys.black_to_move = next();
+        keys
+    })
+}
+
+/// Key for piece standing on the square with bit index index, empty squares have none ///
+pub(crate) fn piece_key(index: usize, piece: &Piece) -> u64 {
+    piece_index(piece.piece_type).map_or(0, |piece_index| {
+        keys().pieces[color_index(piece.color)][piece_index][index]
+    })
+}
+
+impl BoardPosition {
+    /// Keys for the side to move, castling rights and en passant pawn.
+    /// make_move xors these out before a move and back in after it,
+    /// set_piece keeps the piece keys up to date.
+    pub(crate) fn state_key(&self) -> u64 {
+        let keys = keys();
+        let mut key = match self.side_to_move {
+            PieceColor::White => 0,
+            PieceColor::Black => keys.black_to_move,
+        };
+        for (right, castling_key) in self.castling_rights().iter().zip(keys.castling) {
+            if *right {
+                key ^= castling_key;
+            }
+        }
+        // The en passant pawn only matters while a pawn stands next to it to take it
+        let capturable = self.en_passante.filter(|pawn| {
+            [-1, 1].iter().any(|dx| {
+                let beside = CoordinateSet::new(pawn.x + dx, pawn.y);
+                !beside.out_of_bounds()
+                    && *self.get_piece(&beside) == Piece::new(self.side_to_move, Pawn)
+            })
+        });
+        if let Some(pawn) = capturable {
+            key ^= keys.en_passant[pawn.x as usize];
+        }
+        key
+    }
+
+    /// The Zobrist hash worked out from scratch, the hash make_move keeps always equals it ///
+    pub(crate) fn zobrist_hash(&self) -> u64 {
+        let mut hash = self.state_key();
+        for (y, row) in self.board.iter().enumerate() {
+            for (x, piece) in row.iter().enumerate() {
+                hash ^= piece_key(square_index(&CoordinateSet::new(x as i32, y as i32)), piece);
+            }
+        }
+        hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fen::STARTING_FEN;
+    use crate::BoardPosition;
+
+    /// Plays every legal move to depth, checking the kept hash against a fresh one ///
+    fn check_hashes(position: &mut BoardPosition, depth: u32) {
+        assert_eq!(
+            position.hash,
+            position.zobrist_hash(),
+            "{}",
+            position.to_fen()
+        );
+        if depth == 0 {
+            return;
+        }
+        let before = position.hash;
+        for chess_move in position.eval_moves() {
+            let undo = position.make_move(&chess_move);
+            check_hashes(position, depth - 1);
+            position.unmake_move(&chess_move, undo);
+            assert_eq!(position.hash, before);
+        }
+    }
+
+    #[test]
+    fn incremental_matches_scratch() {
+        for fen in [
+            STARTING_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        ] {
+            check_hashes(&mut BoardPosition::from_fen(fen).unwrap(), 2);
+        }
+    }
+
+    #[test]
+    fn transpositions_match() {
+        let play = |moves: &[&str]| {
+            let mut position = BoardPosition::from_fen(STARTING_FEN).unwrap();
+            for &move_text in moves {
+                let chess_move = position.parse_long_algebraic(move_text).unwrap();
+                position.make_move(&chess_move);
+            }
+            position.hash
+        };
+        assert_eq!(
+            play(&["g1f3", "g8f6", "b1c3"]),
+            play(&["b1c3", "g8f6", "g1f3"])
+        );
+        // The kings walked back to the same squares, but can't castle any more
+        assert_ne!(
+            play(&["e2e4", "e7e5", "e1e2", "e8e7", "e2e1", "e7e8"]),
+            play(&["e2e4", "e7e5"])
+        );
+    }
+}